@@ -7,6 +7,46 @@ pub(crate) const PROCESSING_NAME: &str = "processing";
 pub(crate) const CODE_FILE_PATH_NAME: &str = "code_file_path";
 pub(crate) const AUDIO_FILE_PATH_NAME: &str = "audio_file_path";
 pub(crate) const AUDIO_NAME: &str = "audio";
+pub(crate) const BUFFER_SIZE_NAME: &str = "buffer_size";
+pub(crate) const DEFAULT_BUFFER_SIZE: u32 = 1024;
+pub(crate) const BLOCK_SIZES_NAME: &str = "block_sizes";
+pub(crate) const SEED_NAME: &str = "seed";
+pub(crate) const SIZES_NAME: &str = "sizes";
+pub(crate) const TOLERANCE_NAME: &str = "tolerance";
+pub(crate) const DEFAULT_VERIFY_SIZES: &str = "1,64,1024,random";
+pub(crate) const DEFAULT_TOLERANCE: f64 = 1e-9;
+pub(crate) const OUTPUT_FORMAT_NAME: &str = "output_format";
+pub(crate) const DEFAULT_OUTPUT_FORMAT: &str = "float32";
+pub(crate) const OUTPUT_FORMATS: &[&str] = &["pcm16", "pcm24", "pcm32", "float32", "float64"];
+pub(crate) const OUTPUT_CONTAINER_NAME: &str = "output_container";
+pub(crate) const DEFAULT_FLAC_OUTPUT_FORMAT: &str = "pcm24";
+pub(crate) const FLAC_OUTPUT_FORMATS: &[&str] = &["pcm16", "pcm24"];
+pub(crate) const DITHER_NAME: &str = "dither";
+pub(crate) const NOISE_SHAPING_NAME: &str = "noise_shaping";
+pub(crate) const DITHER_SEED_NAME: &str = "dither_seed";
+pub(crate) const SAMPLE_RATE_NAME: &str = "sample_rate";
+pub(crate) const RESTORE_RATE_NAME: &str = "restore_rate";
+pub(crate) const RATE_MATRIX_NAME: &str = "rate_matrix";
+pub(crate) const MULTI_MONO_NAME: &str = "multi_mono";
+pub(crate) const SPLIT_MULTI_MONO_NAME: &str = "split_multi_mono";
+pub(crate) const META_NAME: &str = "meta";
+pub(crate) const META_CHUNKS: &[&str] = &["bext", "info", "markers", "ixml"];
+pub(crate) const PROVENANCE_NAME: &str = "provenance";
+pub(crate) const PARAM_NAME: &str = "param";
+pub(crate) const PRESET_NAME: &str = "preset";
+pub(crate) const PRESETS_NAME: &str = "presets";
+// `--preset all` renders every preset.
+pub(crate) const ALL_PRESETS: &str = "all";
+pub(crate) const AUTOMATION_NAME: &str = "automation";
+pub(crate) const AUTOMATION_RATE_NAME: &str = "automation_rate";
+pub(crate) const MIDI_NAME: &str = "midi";
+pub(crate) const INFO_FILE_NAME: &str = "file";
+pub(crate) const AUDIO_EXTENSIONS: &[&str] = &[
+    "wav", "flac", "aif", "aiff", "aifc", "mp3", "ogg", "oga", "opus",
+];
+pub(crate) const LOSSY_AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "oga", "opus"];
+// Standard MIDI Files, found next to a source file with the same stem.
+pub(crate) const MIDI_EXTENSIONS: &[&str] = &["mid", "midi"];
 
 pub(crate) static PROGRAM_FOLDER: LazyLock<PathBuf> =
     LazyLock::new(|| PathBuf::from("..").join("audio").join("processing"));
@@ -22,6 +62,12 @@ pub(crate) static CPP_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
         .join("processing")
         .join("cpp")
 });
+pub(crate) static PRESETS_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| {
+    PathBuf::from("..")
+        .join("audio")
+        .join("processing")
+        .join("presets")
+});
 pub(crate) static SOURCE_FOLDER: LazyLock<PathBuf> =
     LazyLock::new(|| PathBuf::from("..").join("audio").join("source"));
 pub(crate) static RESULT_FOLDER: LazyLock<PathBuf> =
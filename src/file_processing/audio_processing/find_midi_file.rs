@@ -0,0 +1,49 @@
+use crate::constants::constants::*;
+use std::fs;
+use std::path::Path;
+
+// The MIDI file that goes with `audio_file`: `<name>.mid` or `<name>.midi` (any case) in
+// the same folder, where `name` is the file's stem, or a multi-mono group's name.
+pub(crate) fn find_midi_file(audio_file: &str, name: &str) -> Option<String> {
+    let folder = Path::new(audio_file)
+        .parent()
+        .filter(|folder| !folder.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut matches: Vec<String> = fs::read_dir(folder)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            is_midi_file(path) && path.file_stem().and_then(|stem| stem.to_str()) == Some(name)
+        })
+        .filter_map(|path| path.to_str().map(str::to_string))
+        .collect();
+    // Several spellings of the same name (take.mid and take.MIDI) pick the same one each run.
+    matches.sort();
+    matches.into_iter().next()
+}
+
+pub(crate) fn get_midi_files_from_folder(folder: &str) -> Vec<String> {
+    match fs::read_dir(folder) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| is_midi_file(path))
+            .filter_map(|path| path.to_str().map(str::to_string))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn is_midi_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| {
+                MIDI_EXTENSIONS
+                    .iter()
+                    .any(|supported| ext.eq_ignore_ascii_case(supported))
+            })
+            .unwrap_or(false)
+}
@@ -1,3 +1,4 @@
+use crate::constants::constants::*;
 use std::fs;
 use std::path::Path;
 
@@ -9,7 +10,7 @@ pub(crate) fn get_audio_files_from_folder(source: &str) -> Vec<String> {
                 .filter_map(|entry| {
                     let entry = entry.ok()?;
                     let path = entry.path();
-                    if path.extension().map(|ext| ext == "wav").unwrap_or(false) {
+                    if is_audio_file(&path) {
                         path.to_str().map(|s| s.to_string())
                     } else {
                         None
@@ -25,3 +26,14 @@ pub(crate) fn get_audio_files_from_folder(source: &str) -> Vec<String> {
         vec![source.to_string()]
     }
 }
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .any(|supported| ext.eq_ignore_ascii_case(supported))
+        })
+        .unwrap_or(false)
+}
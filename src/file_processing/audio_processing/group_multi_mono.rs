@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Pro Tools multi-mono channel names, in WAV channel order so a merged group comes out as
+// L R C LFE ... like an interleaved file would. A name's index is its channel mask bit.
+const PRO_TOOLS_CHANNELS: &[&[&str]] = &[
+    &["L"],
+    &["R"],
+    &["C"],
+    &["LFE"],
+    &["Lsr"],
+    &["Rsr"],
+    &["Lc"],
+    &["Rc"],
+    &["Cs"],
+    &["Ls", "Lss"],
+    &["Rs", "Rss"],
+    &["Ts"],
+    &["Ltf"],
+    &["Ctf"],
+    &["Rtf"],
+    &["Ltr"],
+    &["Ctr"],
+    &["Rtr"],
+];
+
+// How sibling files are recognised as channels of one recording.
+#[derive(Clone, Debug)]
+pub(crate) enum GroupingRule {
+    // `take.L.wav`, `take.R.wav`, `take.C.wav`, ...
+    ProTools,
+    // `take_1.wav` .. `take_N.wav`, numbered from 1 without gaps.
+    Numbered,
+    // A custom, ordered list of stem suffixes, e.g. `_left,_right`.
+    Suffixes(Vec<String>),
+}
+
+// Folder, stem without the channel suffix, and lower-case extension of a group's files.
+type GroupKey = (String, String, String);
+
+// A file recognised as one channel of a group.
+struct Member {
+    position: usize,
+    suffix: String,
+    file: String,
+}
+
+// One render input: a single file, or multi-mono files merged in channel order.
+pub(crate) struct InputGroup {
+    // Output files are named after this stem.
+    pub(crate) name: String,
+    pub(crate) files: Vec<String>,
+    // The part of each file's stem after `name`, used to name split outputs.
+    pub(crate) suffixes: Vec<String>,
+    // WAVE_FORMAT_EXTENSIBLE speaker mask of the merged channels, when the rule names them.
+    pub(crate) channel_mask: Option<u32>,
+}
+
+impl InputGroup {
+    pub(crate) fn is_multi_mono(&self) -> bool {
+        self.files.len() > 1
+    }
+}
+
+// Parses a --multi-mono rule: `pro-tools`, `numbered` or `suffixes:<s1>,<s2>,...`.
+pub(crate) fn parse_grouping_rule(spec: &str) -> Result<GroupingRule, String> {
+    match spec {
+        "pro-tools" => Ok(GroupingRule::ProTools),
+        "numbered" => Ok(GroupingRule::Numbered),
+        _ => {
+            let list = spec.strip_prefix("suffixes:").ok_or_else(|| {
+                format!(
+                    "expected pro-tools, numbered or suffixes:<s1>,<s2>,..., got '{}'",
+                    spec
+                )
+            })?;
+            let suffixes: Vec<String> = list.split(',').map(str::to_string).collect();
+            if suffixes.len() < 2 || suffixes.iter().any(String::is_empty) {
+                return Err(format!("'{}' needs at least two non-empty suffixes", spec));
+            }
+            if (1..suffixes.len()).any(|i| suffixes[..i].contains(&suffixes[i])) {
+                return Err(format!("'{}' lists the same suffix twice", spec));
+            }
+            Ok(GroupingRule::Suffixes(suffixes))
+        }
+    }
+}
+
+// Merges sibling files matched by `rules` into multichannel inputs. Rules are tried in order;
+// a group needs at least two members and, for `numbered`, every number from 1 up. Files that
+// do not end up in a group are rendered on their own.
+pub(crate) fn group_multi_mono(files: Vec<String>, rules: &[GroupingRule]) -> Vec<InputGroup> {
+    let mut remaining = files;
+    let mut groups = Vec::new();
+
+    for rule in rules {
+        let mut candidates: BTreeMap<GroupKey, Vec<Member>> = BTreeMap::new();
+        let mut unmatched = Vec::new();
+        for file in remaining {
+            match match_channel(&file, rule) {
+                Some((key, position, suffix)) => candidates.entry(key).or_default().push(Member {
+                    position,
+                    suffix,
+                    file,
+                }),
+                None => unmatched.push(file),
+            }
+        }
+
+        for ((_, name, _), mut members) in candidates {
+            members.sort_by_key(|member| member.position);
+            let distinct = members
+                .windows(2)
+                .all(|pair| pair[0].position != pair[1].position);
+            let complete = !matches!(rule, GroupingRule::Numbered)
+                || members
+                    .iter()
+                    .enumerate()
+                    .all(|(i, member)| member.position == i + 1);
+            if members.len() >= 2 && distinct && complete {
+                let channel_mask = matches!(rule, GroupingRule::ProTools).then(|| {
+                    members
+                        .iter()
+                        .fold(0, |mask, member| mask | 1 << member.position)
+                });
+                let (suffixes, files) = members
+                    .into_iter()
+                    .map(|member| (member.suffix, member.file))
+                    .unzip();
+                groups.push(InputGroup {
+                    name,
+                    files,
+                    suffixes,
+                    channel_mask,
+                });
+            } else {
+                unmatched.extend(members.into_iter().map(|member| member.file));
+            }
+        }
+        remaining = unmatched;
+    }
+
+    groups.extend(remaining.into_iter().map(|file| InputGroup {
+        name: file_stem(&file),
+        files: vec![file],
+        suffixes: vec![String::new()],
+        channel_mask: None,
+    }));
+    groups
+}
+
+// Returns the group key, channel position and stem suffix of `file` under `rule`.
+fn match_channel(file: &str, rule: &GroupingRule) -> Option<(GroupKey, usize, String)> {
+    let path = Path::new(file);
+    let stem = path.file_stem()?.to_str()?;
+    let (base, position, suffix) = match rule {
+        GroupingRule::ProTools => {
+            let (base, channel) = stem.rsplit_once('.')?;
+            let position = PRO_TOOLS_CHANNELS
+                .iter()
+                .position(|names| names.iter().any(|name| name.eq_ignore_ascii_case(channel)))?;
+            (base, position, format!(".{}", channel))
+        }
+        GroupingRule::Numbered => {
+            let (base, number) = stem.rsplit_once('_')?;
+            if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            (base, number.parse().ok()?, format!("_{}", number))
+        }
+        GroupingRule::Suffixes(suffixes) => {
+            suffixes.iter().enumerate().find_map(|(position, suffix)| {
+                stem.strip_suffix(suffix.as_str())
+                    .map(|base| (base, position, suffix.clone()))
+            })?
+        }
+    };
+    if base.is_empty() {
+        return None;
+    }
+
+    let directory = path.parent()?.to_str()?.to_string();
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(((directory, base.to_string(), extension), position, suffix))
+}
+
+fn file_stem(file: &str) -> String {
+    Path::new(file)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Files `rules` leaves on their own, sorted.
+    fn ungrouped(files: &[&str], rules: &[GroupingRule]) -> Vec<String> {
+        let files = files.iter().map(|file| file.to_string()).collect();
+        let mut singles: Vec<String> = group_multi_mono(files, rules)
+            .into_iter()
+            .filter(|group| !group.is_multi_mono())
+            .flat_map(|group| group.files)
+            .collect();
+        singles.sort();
+        singles
+    }
+
+    fn sorted(files: &[&str]) -> Vec<String> {
+        let mut files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn parses_grouping_rules() {
+        assert!(matches!(
+            parse_grouping_rule("pro-tools"),
+            Ok(GroupingRule::ProTools)
+        ));
+        assert!(matches!(
+            parse_grouping_rule("numbered"),
+            Ok(GroupingRule::Numbered)
+        ));
+        match parse_grouping_rule("suffixes:_left,_right") {
+            Ok(GroupingRule::Suffixes(suffixes)) => assert_eq!(suffixes, ["_left", "_right"]),
+            _ => panic!("suffixes:_left,_right was not parsed"),
+        }
+        for spec in [
+            "",
+            "protools",
+            "suffixes:",
+            "suffixes:_left",
+            "suffixes:_left,",
+            "suffixes:_l,_r,_l",
+        ] {
+            assert!(parse_grouping_rule(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+
+    #[test]
+    fn merges_pro_tools_channels_in_wav_order() {
+        let files = vec![
+            "src/take.R.wav".to_string(),
+            "src/take.c.wav".to_string(),
+            "src/take.L.wav".to_string(),
+            "src/other.wav".to_string(),
+        ];
+        let groups = group_multi_mono(files, &[GroupingRule::ProTools]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "take");
+        assert_eq!(
+            groups[0].files,
+            ["src/take.L.wav", "src/take.R.wav", "src/take.c.wav"]
+        );
+        assert_eq!(groups[0].suffixes, [".L", ".R", ".c"]);
+        assert_eq!(groups[0].channel_mask, Some(0b111));
+        assert!(!groups[1].is_multi_mono());
+        assert_eq!(groups[1].files, ["src/other.wav"]);
+    }
+
+    #[test]
+    fn keeps_folders_and_extensions_apart() {
+        let files = ["a/take.L.wav", "b/take.R.wav", "a/take.R.flac"];
+        assert_eq!(ungrouped(&files, &[GroupingRule::ProTools]), sorted(&files));
+    }
+
+    #[test]
+    fn rejects_incomplete_or_ambiguous_groups() {
+        // Numbered groups start at 1 without gaps.
+        let files = ["s/take_1.wav", "s/take_3.wav", "s/mix_2.wav", "s/mix_3.wav"];
+        assert_eq!(ungrouped(&files, &[GroupingRule::Numbered]), sorted(&files));
+        // Two files for the same channel.
+        let files = ["s/take.Ls.wav", "s/take.Lss.wav", "s/take.R.wav"];
+        assert_eq!(ungrouped(&files, &[GroupingRule::ProTools]), sorted(&files));
+    }
+
+    #[test]
+    fn tries_rules_in_order() {
+        let files = [
+            "s/take_1.wav",
+            "s/take_2.wav",
+            "s/vox_left.wav",
+            "s/vox_right.wav",
+        ];
+        let rules = [
+            GroupingRule::Numbered,
+            parse_grouping_rule("suffixes:_left,_right").unwrap(),
+        ];
+        let groups = group_multi_mono(files.map(str::to_string).to_vec(), &rules);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            (groups[0].name.as_str(), groups[0].channel_mask),
+            ("take", None)
+        );
+        assert_eq!(groups[1].name, "vox");
+        assert_eq!(groups[1].suffixes, ["_left", "_right"]);
+    }
+}
@@ -1,2 +1,5 @@
+pub(crate) mod find_midi_file;
 pub(crate) mod get_audio_files_from_folder;
+pub(crate) mod group_multi_mono;
+pub(crate) mod read_provenance;
 pub(crate) mod replace_audio_files;
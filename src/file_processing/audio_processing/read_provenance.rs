@@ -0,0 +1,360 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// What `--provenance` wrote into a rendered file.
+pub(crate) struct Provenance {
+    // (field, value) pairs in file order, e.g. ("LANGUAGE", "rs").
+    pub(crate) fields: Vec<(String, String)>,
+    // The bext description, when the file has one.
+    pub(crate) description: Option<String>,
+}
+
+// Reads the provenance of a WAV (PLAYDSP element of the iXML chunk) or FLAC (PLAYDSP_*
+// Vorbis comments) file.
+pub(crate) fn read_provenance(path: &Path) -> io::Result<Provenance> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)?;
+    match &magic {
+        b"RIFF" | b"RF64" => read_wav_provenance(&mut file),
+        b"fLaC" => read_flac_provenance(&mut file),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a WAV or FLAC file",
+        )),
+    }
+}
+
+fn read_wav_provenance(file: &mut BufReader<File>) -> io::Result<Provenance> {
+    let mut provenance = Provenance {
+        fields: Vec::new(),
+        description: None,
+    };
+    let mut header = [0u8; 8];
+    file.read_exact(&mut header)?;
+    if &header[4..8] != b"WAVE" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a WAVE file",
+        ));
+    }
+
+    // An RF64 data chunk gives its real size in the ds64 chunk before it.
+    let mut ds64_data_size = None;
+    loop {
+        let mut chunk_header = [0u8; 8];
+        match file.read_exact(&mut chunk_header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let id = &chunk_header[0..4];
+        let mut size = u32::from_le_bytes(chunk_header[4..8].try_into().unwrap()) as u64;
+        if id == b"data" && size == u32::MAX as u64 {
+            size = ds64_data_size.unwrap_or(size);
+        }
+
+        match id {
+            b"ds64" | b"bext" | b"iXML" => {
+                let body = read_body(file, size)?;
+                if id == b"ds64" && body.len() >= 16 {
+                    ds64_data_size = Some(u64::from_le_bytes(body[8..16].try_into().unwrap()));
+                } else if id == b"bext" {
+                    let description = text_field(&body[..body.len().min(256)]);
+                    if !description.is_empty() {
+                        provenance.description = Some(description);
+                    }
+                } else if id == b"iXML" {
+                    provenance.fields = ixml_fields(&String::from_utf8_lossy(&body));
+                }
+                if size % 2 == 1 {
+                    file.seek_relative(1)?;
+                }
+            }
+            _ => {
+                file.seek(SeekFrom::Current((size + size % 2) as i64))?;
+            }
+        }
+    }
+
+    Ok(provenance)
+}
+
+// The children of the PLAYDSP element, e.g. `<LANGUAGE>rs</LANGUAGE>`.
+fn ixml_fields(document: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let element = match (document.find("<PLAYDSP>"), document.find("</PLAYDSP>")) {
+        (Some(start), Some(end)) if start < end => &document[start + "<PLAYDSP>".len()..end],
+        _ => return fields,
+    };
+
+    let mut rest = element;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        let name = &rest[open + 1..open + close];
+        let after = &rest[open + close + 1..];
+        let closing_tag = format!("</{}>", name);
+        let Some(end) = after.find(&closing_tag) else {
+            break;
+        };
+        let value = after[..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&");
+        fields.push((name.to_string(), value));
+        rest = &after[end + closing_tag.len()..];
+    }
+    fields
+}
+
+fn read_flac_provenance(file: &mut BufReader<File>) -> io::Result<Provenance> {
+    let mut provenance = Provenance {
+        fields: Vec::new(),
+        description: None,
+    };
+    loop {
+        let mut block_header = [0u8; 4];
+        file.read_exact(&mut block_header)?;
+        let is_last = block_header[0] & 0x80 != 0;
+        let block_type = block_header[0] & 0x7F;
+        let length = u32::from_be_bytes([0, block_header[1], block_header[2], block_header[3]]);
+
+        if block_type == 4 {
+            let body = read_body(file, length as u64)?;
+            provenance = vorbis_comment_provenance(&body);
+        } else {
+            file.seek(SeekFrom::Current(length as i64))?;
+        }
+        if is_last {
+            break;
+        }
+    }
+    Ok(provenance)
+}
+
+// PLAYDSP_* entries of a VORBIS_COMMENT block, without the prefix, and the DESCRIPTION
+// the bext description is written as.
+fn vorbis_comment_provenance(body: &[u8]) -> Provenance {
+    let mut provenance = Provenance {
+        fields: Vec::new(),
+        description: None,
+    };
+    let read_u32 = |offset: usize| {
+        body.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    };
+
+    let Some(vendor_length) = read_u32(0) else {
+        return provenance;
+    };
+    let mut offset = 4 + vendor_length;
+    let Some(count) = read_u32(offset) else {
+        return provenance;
+    };
+    offset += 4;
+    for _ in 0..count {
+        let Some(length) = read_u32(offset) else {
+            break;
+        };
+        let Some(comment) = body.get(offset + 4..offset + 4 + length) else {
+            break;
+        };
+        offset += 4 + length;
+        let comment = String::from_utf8_lossy(comment);
+        let Some((name, value)) = comment.split_once('=') else {
+            continue;
+        };
+        let name = name.to_ascii_uppercase();
+        if name == "DESCRIPTION" {
+            provenance.description = Some(value.to_string());
+        } else if let Some(field) = name.strip_prefix("PLAYDSP_") {
+            provenance
+                .fields
+                .push((field.to_string(), value.to_string()));
+        }
+    }
+    provenance
+}
+
+// Reads a chunk or block body of `length` bytes, as given by the file, checking first that
+// the file has that many bytes left.
+fn read_body(file: &mut BufReader<File>, length: u64) -> io::Result<Vec<u8>> {
+    let remaining = file
+        .get_ref()
+        .metadata()?
+        .len()
+        .saturating_sub(file.stream_position()?);
+    if length > remaining {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "a {} byte chunk runs past the end of the file ({} bytes left)",
+                length, remaining
+            ),
+        ));
+    }
+    let mut body = vec![0u8; length as usize];
+    file.read_exact(&mut body)?;
+    Ok(body)
+}
+
+// A NUL-padded ASCII field.
+fn text_field(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn riff(magic: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = b"WAVE"
+            .iter()
+            .chain(chunks.concat().iter())
+            .copied()
+            .collect();
+        let mut file = magic.to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
+
+    // Reads the provenance of `contents` written to a scratch file.
+    fn provenance_of(name: &str, contents: &[u8]) -> io::Result<Provenance> {
+        let path = std::env::temp_dir().join(format!(
+            "playdsp_provenance_{}_{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, contents)?;
+        let provenance = read_provenance(&path);
+        std::fs::remove_file(&path)?;
+        provenance
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    const IXML: &str = "<?xml version=\"1.0\"?><BWFXML><SCENE>4</SCENE><PLAYDSP>\
+        <LANGUAGE>rs</LANGUAGE><PARAMS>cutoff=1200 &amp; q&lt;1</PARAMS></PLAYDSP></BWFXML>";
+
+    #[test]
+    fn reads_wav_ixml_and_bext_description() {
+        let mut bext = vec![0u8; 602];
+        bext[..22].copy_from_slice(b"playdsp rs cutoff=1200");
+        let file = riff(
+            b"RIFF",
+            &[
+                chunk(b"fmt ", &[0; 16]),
+                chunk(b"bext", &bext),
+                chunk(b"data", &[0; 7]),
+                chunk(b"iXML", IXML.as_bytes()),
+            ],
+        );
+        let provenance = provenance_of("bext.wav", &file).unwrap();
+        assert_eq!(
+            provenance.fields,
+            fields(&[("LANGUAGE", "rs"), ("PARAMS", "cutoff=1200 & q<1")])
+        );
+        assert_eq!(
+            provenance.description.as_deref(),
+            Some("playdsp rs cutoff=1200")
+        );
+    }
+
+    #[test]
+    fn reads_rf64_past_the_data_chunk() {
+        // The data chunk's size field is 0xFFFFFFFF; its real size is in ds64.
+        let mut ds64 = vec![0u8; 28];
+        ds64[8..16].copy_from_slice(&6u64.to_le_bytes());
+        let mut data = b"data".to_vec();
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        let file = riff(
+            b"RF64",
+            &[
+                chunk(b"ds64", &ds64),
+                chunk(b"fmt ", &[0; 16]),
+                data,
+                chunk(b"iXML", IXML.as_bytes()),
+            ],
+        );
+        let provenance = provenance_of("rf64.wav", &file).unwrap();
+        assert_eq!(provenance.fields.len(), 2);
+        assert_eq!(provenance.description, None);
+    }
+
+    #[test]
+    fn reads_flac_vorbis_comments() {
+        let comments = [
+            "TITLE=Take 3",
+            "playdsp_language=cpp",
+            "DESCRIPTION=playdsp cpp",
+        ];
+        let mut body = 7u32.to_le_bytes().to_vec();
+        body.extend_from_slice(b"flacenc");
+        body.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            body.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            body.extend_from_slice(comment.as_bytes());
+        }
+        let mut file = b"fLaC".to_vec();
+        file.extend_from_slice(&[0, 0, 0, 34]);
+        file.extend_from_slice(&[0; 34]);
+        file.extend_from_slice(&[0x84, 0, 0, body.len() as u8]);
+        file.extend(body);
+
+        let provenance = provenance_of("comments.flac", &file).unwrap();
+        assert_eq!(provenance.fields, fields(&[("LANGUAGE", "cpp")]));
+        assert_eq!(provenance.description.as_deref(), Some("playdsp cpp"));
+    }
+
+    #[test]
+    fn finds_nothing_without_provenance() {
+        let file = riff(
+            b"RIFF",
+            &[chunk(b"fmt ", &[0; 16]), chunk(b"data", &[0; 4])],
+        );
+        let provenance = provenance_of("plain.wav", &file).unwrap();
+        assert!(provenance.fields.is_empty());
+        assert_eq!(provenance.description, None);
+        assert!(provenance_of("text.txt", b"not audio").is_err());
+    }
+
+    #[test]
+    fn rejects_chunks_longer_than_the_file() {
+        let mut ixml = b"iXML".to_vec();
+        ixml.extend_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+        ixml.extend_from_slice(b"<BWFXML>");
+        let file = riff(b"RIFF", &[chunk(b"fmt ", &[0; 16]), ixml]);
+        let error = provenance_of("oversized.wav", &file).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("8 bytes left"), "{}", error);
+
+        // A VORBIS_COMMENT block claiming 16 MiB in a 50 byte file.
+        let mut file = b"fLaC".to_vec();
+        file.extend_from_slice(&[0, 0, 0, 34]);
+        file.extend_from_slice(&[0; 34]);
+        file.extend_from_slice(&[0x84, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+        let error = provenance_of("oversized.flac", &file).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
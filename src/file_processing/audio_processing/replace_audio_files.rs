@@ -1,4 +1,5 @@
 use crate::constants::constants::*;
+use crate::file_processing::audio_processing::find_midi_file::*;
 use crate::file_processing::audio_processing::get_audio_files_from_folder::*;
 use std::fs::{copy, remove_file};
 use std::io::Error;
@@ -12,7 +13,7 @@ pub(crate) fn replace_audio_files(input_folder: &str) -> io::Result<()> {
     if input_wav_files.is_empty() {
         return Err(Error::new(
             io::ErrorKind::NotFound,
-            "No valid .wav files found in the replace folder",
+            "No valid audio files found in the replace folder",
         ));
     }
 
@@ -37,15 +38,34 @@ pub(crate) fn replace_audio_files(input_folder: &str) -> io::Result<()> {
         copy(input_path, destination_path)?;
     }
 
+    // MIDI files travel with their sources: every one in a folder, or the one next to a
+    // single file.
+    let midi_files = if Path::new(input_folder).is_dir() {
+        get_midi_files_from_folder(input_folder)
+    } else {
+        Path::new(input_folder)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| find_midi_file(input_folder, stem))
+            .into_iter()
+            .collect()
+    };
+    for midi_file in midi_files {
+        let midi_path = Path::new(&midi_file);
+        if let Some(file_name) = midi_path.file_name() {
+            copy(midi_path, SOURCE_FOLDER.join(file_name))?;
+        }
+    }
+
     if input_wav_files_len == 1 {
         println!(
-            "Valid .wav file '{}' has been copied to '{}'.",
+            "Valid audio file '{}' has been copied to '{}'.",
             input_folder,
             SOURCE_FOLDER.display()
         );
     } else {
         println!(
-            "All valid .wav files from '{}' have been copied to '{}'.",
+            "All valid audio files from '{}' have been copied to '{}'.",
             input_folder,
             SOURCE_FOLDER.display()
         );
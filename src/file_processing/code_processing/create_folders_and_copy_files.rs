@@ -8,6 +8,7 @@ pub(crate) fn create_folders_and_copy_files(base_dir: &str) {
     let rust_dir = processing_dir.join("rust");
     let cpp_dir = processing_dir.join("cpp");
     let tests_dir = processing_dir.join("tests");
+    let presets_dir = processing_dir.join(PRESETS_NAME);
     let result_dir = audio_dir.join(RESULT_NAME);
     let source_dir = audio_dir.join(SOURCE_NAME);
 
@@ -18,6 +19,7 @@ pub(crate) fn create_folders_and_copy_files(base_dir: &str) {
     create_dir_all(&rust_dir).expect("Failed to create 'rust' directory");
     create_dir_all(&cpp_dir).expect("Failed to create 'cpp' directory");
     create_dir_all(&tests_dir).expect("Failed to create 'tests' directory");
+    create_dir_all(&presets_dir).expect("Failed to create 'presets' directory");
     create_dir_all(&result_dir)
         .expect(&*("Failed to create '".to_owned() + RESULT_NAME + "' directory"));
     create_dir_all(&source_dir)
@@ -27,140 +29,249 @@ pub(crate) fn create_folders_and_copy_files(base_dir: &str) {
 // rust_process_audio.rs — PlayDSP Rust DSP entry point
 // ============================================================================
 //
-// BUFFER-BY-BUFFER PROCESSING
-// ----------------------------
-// rust_process() is called once per 1024-sample buffer, sequentially, for
-// every buffer in the audio file. Local variables are re-created on every
-// call — so filter states, delay-line read/write heads, envelope followers,
-// and any other data that must carry over from one buffer to the next must
-// live *outside* this function.
+// AUDIO PROCESSOR
+// ---------------
+// Your DSP is a struct implementing AudioProcessor, registered at the bottom
+// of this file with register_processor!. The runtime creates a fresh
+// instance for every audio file, so fields start from their initial values
+// on each render — no globals or locks needed.
 //
+// For every audio file the runtime calls:
+//   set_parameter(name, value)                      once per declared parameter
+//   set_channel_layout(layout)                      before prepare
+//   prepare(sample_rate, max_block, num_channels)  before the first buffer
+//   reset()                                         right after prepare
+//   process(input, output)                          once per buffer
+// process() is called once per buffer (1024 samples unless --buffer-size is
+// given). With --block-sizes the length changes from call to call, so always
+// take the sample count from the buffers you are given.
 //
-// PERSISTENT STATE — LazyLock<Mutex<State>>
-// ------------------------------------------
-// All DSP logic lives inside State::process(). Add fields to State for any
-// data that must persist across buffer calls (filter registers, delay lines,
-// envelope followers, etc.).
+// Use prepare() to design filters for the file's sample rate and to size
+// per-channel state, and reset() to clear it. Both are optional, as is
+// set_channel_layout(), which gives each channel's speaker position (LFE,
+// centre, height, ...) for surround files.
+// If your processor delays the signal (lookahead, linear-phase filters),
+// return the delay from latency_samples() and the runtime trims it from the
+// output.
 //
-// The channel count is only known at call time, so size any per-channel
-// Vecs lazily — check the length and resize before use:
+// PARAMETERS
+// ----------
+// parameters() declares named controls with a range, default and unit.
+// `playdsp --param gain_db=-6` sets one for a render without recompiling;
+// parameters left out get their default. The values are added to the
+// output file name.
 //
-//   if self.prev_sample.len() < input.len() {
-//       self.prev_sample.resize(input.len(), 0.0);
-//   }
+// MIDI
+// ----
+// For instruments and MIDI-controlled effects, implement receive_midi(). It
+// gets the note, CC and pitch-bend events of `source/<name>.mid` (or
+// `playdsp --midi <file>`) that fall in the next buffer, just before
+// process(); each MidiEvent's `offset` is the sample in that buffer.
+//
+// The older free-function form — a public rust_process(input, output) over
+// &Vec<Vec<f64>> buffers with optional rust_parameters / rust_set_parameter /
+// rust_set_channel_layout / rust_prepare / rust_reset / rust_release /
+// rust_receive_midi — is still supported in place of a registered processor.
 //
 // ============================================================================
 
-use std::sync::{LazyLock, Mutex};
+use crate::processor::{AudioBuffer, AudioProcessor, Parameter};
+
+const DEFAULT_GAIN_DB: f64 = -12.0;
 
-struct State {
+pub struct Gain {
+    gain_linear: f64,
     // Add per-channel DSP state here.
+    // Example: sample_rate: f64,
     // Example: prev_sample: Vec<f64>,
 }
 
-impl State {
-    fn process(&mut self, input: &Vec<Vec<f64>>, output: &mut Vec<Vec<f64>>) {
-        let gain_db = -12.0;
-        let gain_linear = 10.0_f64.powf(gain_db / 20.0);
+impl Gain {
+    pub fn new() -> Self {
+        Gain {
+            gain_linear: 10.0_f64.powf(DEFAULT_GAIN_DB / 20.0),
+        }
+    }
+}
+
+impl AudioProcessor for Gain {
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter::new("gain_db", -60.0, 24.0, DEFAULT_GAIN_DB, "dB")]
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f64) {
+        if name == "gain_db" {
+            self.gain_linear = 10.0_f64.powf(value / 20.0);
+        }
+    }
+
+    fn prepare(&mut self, _sample_rate: f64, _max_block: usize, _num_channels: usize) {
+        // Store the sample rate and size per-channel state here.
+        // Example: self.sample_rate = _sample_rate;
+        // Example: self.prev_sample = vec![0.0; _num_channels];
+    }
+
+    fn reset(&mut self) {
+        // Clear per-channel state here.
+        // Example: self.prev_sample.fill(0.0);
+    }
 
-        for (in_channel, out_channel) in input.iter().zip(output.iter_mut()) {
+    fn process(&mut self, input: &AudioBuffer, output: &mut AudioBuffer) {
+        for (in_channel, out_channel) in input.channels().zip(output.channels_mut()) {
             for (in_sample, out_sample) in in_channel.iter().zip(out_channel.iter_mut()) {
-                *out_sample = in_sample * gain_linear;
+                *out_sample = in_sample * self.gain_linear;
             }
         }
     }
 }
 
-static STATE: LazyLock<Mutex<State>> =
-    LazyLock::new(|| Mutex::new(State {}));
-
-pub fn rust_process(input: &Vec<Vec<f64>>, output: &mut Vec<Vec<f64>>) {
-    STATE.lock().unwrap().process(input, output);
-}"#;
+crate::register_processor!(Gain::new());"#;
 
     let cpp_file_content = r#"// ============================================================================
 // cpp_process_audio.cpp — PlayDSP C++ DSP entry point
 // ============================================================================
 //
-// BUFFER-BY-BUFFER PROCESSING
-// ----------------------------
-// cpp_process() is called once per 1024-sample buffer, sequentially, for
-// every buffer in the audio file. Local variables are destroyed at the end
-// of each call, so filter states, delay-line heads, envelope followers, and
-// any data that must carry over between buffers must live outside this
-// function.
+// PROCESSOR INSTANCES — cpp_create() / cpp_destroy()
+// ---------------------------------------------------
+// Your DSP is a Processor object. The runtime calls cpp_create() once per
+// audio file and passes the returned handle to every other call, so each
+// render — and each test — gets its own instance with no globals or locks.
 //
+// For every audio file the runtime calls:
+//   cpp_create(sample_rate, num_channels, max_block)  before the first buffer
+//   cpp_set_parameter(handle, name, value)             per declared parameter
+//   cpp_set_channel_layout(handle, mask, roles, n)     right after that
+//   cpp_reset(handle)                                  right after that
+//   cpp_process_planar(handle, ...)                    once per buffer
+//   cpp_destroy(handle)                                after the last buffer
+// cpp_reset() is optional, and so is cpp_set_channel_layout(), which gets the
+// file's WAVE_FORMAT_EXTENSIBLE channel mask and, per channel, the mask bit
+// of its speaker (3 is LFE, 2 centre) or -1 when it has none.
 //
-// PERSISTENT STATE — State::process() + std::scoped_lock
-// -------------------------------------------------------
-// All DSP logic lives inside State::process(). Add fields to State for any
-// data that must persist across buffer calls (filter registers, delay lines,
-// envelope followers, etc.).
+// PARAMETERS
+// ----------
+// cpp_parameter_info() declares named controls with a range, default and
+// unit, one per index, returning false past the last. `playdsp --param
+// gain_db=-6` sets one for a render without recompiling; parameters left out
+// get their default. The values are added to the output file name.
 //
-// cpp_process() also holds static input/output working buffers that are
-// resized lazily — avoiding repeated heap allocation on every call.
-// All statics are protected by the same std::scoped_lock.
+// BUFFER-BY-BUFFER PROCESSING
+// ----------------------------
+// cpp_process_planar() is called once per buffer (1024 samples unless
+// --buffer-size is given), sequentially, for every buffer in the audio file.
+// With --block-sizes num_samples changes from call to call, up to max_block.
+// input[channel] and output[channel] point straight into the runtime's
+// channel buffers, so there is nothing to interleave or copy.
+// Local variables are destroyed at the end of each call, so filter states,
+// delay-line heads, envelope followers, and any data that must carry over
+// between buffers must be members of Processor.
 //
-// The channel count is only known at call time, so size any per-channel
-// vectors lazily — check the size and resize before use:
+// MIDI
+// ----
+// Define cpp_receive_midi(handle, events, count) to get the note, CC and
+// pitch-bend events of `source/<name>.mid` (or `playdsp --midi <file>`) that
+// fall in the next buffer, just before it is processed. Each event is
+//   struct PlaydspMidiEvent { uint32_t offset; uint8_t status, data1, data2,
+//                             reserved; };
+// with `offset` the sample in that buffer and the raw MIDI bytes after it.
 //
-//   if (prev_sample.size() < num_channels)
-//       prev_sample.resize(num_channels, 0.0);
+// Older forms are still supported: cpp_process(handle, ...) with
+// interleaved buffers instead of cpp_process_planar, and the global
+// cpp_process() without a handle (with optional cpp_set_parameter(name,
+// value) / cpp_prepare / cpp_reset / cpp_release) when cpp_create is not
+// defined.
 //
 // ============================================================================
 
 #include <cstddef>
 #include <cmath>
-#include <mutex>
+#include <cstring>
 #include <vector>
 
-struct State {
+struct ParameterInfo {
+    const char* name;
+    double min_value;
+    double max_value;
+    double default_value;
+    const char* unit;
+};
+
+static const ParameterInfo kParameters[] = {
+    {"gain_db", -60.0, 24.0, -12.0, "dB"},
+};
+
+struct Processor {
+    double sample_rate;
+    double gain_db = -12.0;
     // Add per-channel DSP state here.
     // Example: std::vector<double> prev_sample;
 
-    void process(std::vector<std::vector<double>>& input_vector,
-                 std::size_t num_channels, std::size_t num_samples,
-                 std::vector<std::vector<double>>& output_vector) {
-        double gain_db     = -12.0;
+    Processor(double sample_rate, [[maybe_unused]] std::size_t num_channels,
+              [[maybe_unused]] std::size_t max_block)
+        : sample_rate(sample_rate) {
+        // Size per-channel state here.
+        // Example: prev_sample.assign(num_channels, 0.0);
+    }
+
+    void reset() {
+        // Clear per-channel state here.
+        // Example: std::fill(prev_sample.begin(), prev_sample.end(), 0.0);
+    }
+
+    void set_parameter(const char* name, double value) {
+        if (std::strcmp(name, "gain_db") == 0) {
+            gain_db = value;
+        }
+    }
+
+    void process(const double* const* input, double* const* output,
+                 std::size_t num_channels, std::size_t num_samples) {
         double gain_linear = std::pow(10.0, gain_db / 20.0);
 
         for (std::size_t channel = 0; channel < num_channels; channel++) {
             for (std::size_t sample = 0; sample < num_samples; sample++) {
-                output_vector[channel][sample] =
-                    input_vector[channel][sample] * gain_linear;
+                output[channel][sample] = input[channel][sample] * gain_linear;
             }
         }
     }
 };
 
-extern "C" void cpp_process(const double* input, size_t num_channels,
-                             size_t num_samples, double* output) {
-    static std::mutex                        state_mutex;
-    static State                             state;
-    static std::vector<std::vector<double>>  input_vector;
-    static std::vector<std::vector<double>>  output_vector;
-    std::scoped_lock lock(state_mutex);
-
-    // Resize working buffers lazily when dimensions change.
-    if (input_vector.size() != num_channels ||
-        (!input_vector.empty() && input_vector[0].size() != num_samples)) {
-        input_vector.assign(num_channels,  std::vector<double>(num_samples, 0.0));
-        output_vector.assign(num_channels, std::vector<double>(num_samples, 0.0));
+extern "C" void* cpp_create(double sample_rate, size_t num_channels,
+                             size_t max_block) {
+    return new Processor(sample_rate, num_channels, max_block);
+}
+
+extern "C" bool cpp_parameter_info(size_t index, const char** name,
+                                   double* min_value, double* max_value,
+                                   double* default_value, const char** unit) {
+    if (index >= sizeof(kParameters) / sizeof(kParameters[0])) {
+        return false;
     }
+    const ParameterInfo& parameter = kParameters[index];
+    *name = parameter.name;
+    *min_value = parameter.min_value;
+    *max_value = parameter.max_value;
+    *default_value = parameter.default_value;
+    *unit = parameter.unit;
+    return true;
+}
 
-    // Deinterleave: interleaved → [channel][sample].
-    std::size_t k = 0;
-    for (std::size_t sample = 0; sample < num_samples; sample++)
-        for (std::size_t channel = 0; channel < num_channels; channel++)
-            input_vector[channel][sample] = input[k++];
+extern "C" void cpp_set_parameter(void* handle, const char* name, double value) {
+    static_cast<Processor*>(handle)->set_parameter(name, value);
+}
 
-    state.process(input_vector, num_channels, num_samples, output_vector);
+extern "C" void cpp_reset(void* handle) {
+    static_cast<Processor*>(handle)->reset();
+}
 
-    // Re-interleave: [channel][sample] → interleaved.
-    k = 0;
-    for (std::size_t sample = 0; sample < num_samples; sample++)
-        for (std::size_t channel = 0; channel < num_channels; channel++)
-            output[k++] = output_vector[channel][sample];
+extern "C" void cpp_destroy(void* handle) {
+    delete static_cast<Processor*>(handle);
+}
+
+extern "C" void cpp_process_planar(void* handle, const double* const* input,
+                                    double* const* output, size_t num_channels,
+                                    size_t num_samples) {
+    static_cast<Processor*>(handle)->process(input, output, num_channels, num_samples);
 }"#;
 
     let cpp_tests_file_content = r#"// ============================================================================
@@ -172,17 +283,20 @@ extern "C" void cpp_process(const double* input, size_t num_channels,
 // Tests call crate::cpp_process_audio_wrapper() directly — the same safe
 // Rust wrapper used during audio file processing. No audio files are needed.
 //
-// NOTE ON STATE: The C++ DSP state is held in statics inside cpp_process().
-// Stateless DSP (gain, EQ) — tests are fully independent.
-// Stateful DSP (filters, delays) — call cpp_process_audio_wrapper() a few
-// times first to flush transient state between tests.
+// NOTE ON STATE: every test runs on its own thread and the wrappers give
+// each thread its own cpp_create() instance (48 kHz unless the test calls
+// crate::cpp_prepare_wrapper() first), so tests are fully independent.
+// crate::CppProcessor::new() creates further instances when a test needs
+// more than one. With the older global cpp_process() the state is shared:
+// call crate::cpp_prepare_wrapper() and crate::cpp_reset_wrapper() at the
+// start of each test to clear it.
 // ============================================================================
 
-const BUFFER_SIZE: usize = 1024;
 const TOLERANCE: f64 = 1e-9;
 
+// Buffers use the size passed to `playdsp test --buffer-size` (default 1024).
 fn make_buffer(value: f64, channels: usize) -> Vec<Vec<f64>> {
-    vec![vec![value; BUFFER_SIZE]; channels]
+    vec![vec![value; crate::test_buffer_size()]; channels]
 }
 
 // Verify the default C++ starter code applies exactly -12 dB of gain.
@@ -242,23 +356,34 @@ fn test_cpp_buffer_dimensions_preserved() {
 // Run with: playdsp test
 // Run Rust tests only: playdsp test --rust
 //
-// These are standard Rust unit tests. rust_process() is called directly with
-// synthetic buffers so you get instant, deterministic feedback without needing
-// an audio file.
+// These are standard Rust unit tests. render() creates the registered
+// processor, prepares it and runs one buffer through process(), so you get
+// instant, deterministic feedback without needing an audio file.
 //
-// NOTE ON STATE: The DSP State is a global singleton (LazyLock<Mutex<State>>).
-// Stateless DSP (gain, EQ) — tests are fully independent.
-// Stateful DSP (filters, delays) — call rust_process() a few times first to
-// flush transient state, or reset State fields manually between tests.
+// NOTE ON STATE: every render() call creates a new processor, so tests are
+// fully independent — even for stateful DSP (filters, delays).
+// If your file still uses the free rust_process() function, call it directly
+// instead of render().
 // ============================================================================
 
-use super::rust_process_audio::rust_process;
+use super::rust_process_audio::create_processor;
+use crate::processor::AudioBuffer;
 
-const BUFFER_SIZE: usize = 1024;
 const TOLERANCE: f64 = 1e-9;
+const SAMPLE_RATE: f64 = 48000.0;
 
-fn make_buffer(value: f64, channels: usize) -> Vec<Vec<f64>> {
-    vec![vec![value; BUFFER_SIZE]; channels]
+// Buffers use the size passed to `playdsp test --buffer-size` (default 1024).
+fn make_buffer(value: f64, channels: usize) -> AudioBuffer {
+    AudioBuffer::from_channels(vec![vec![value; crate::test_buffer_size()]; channels])
+}
+
+fn render(input: &AudioBuffer) -> AudioBuffer {
+    let mut processor = create_processor();
+    processor.prepare(SAMPLE_RATE, input.num_samples(), input.num_channels());
+    processor.reset();
+    let mut output = AudioBuffer::new(input.num_channels(), input.num_samples());
+    processor.process(input, &mut output);
+    output
 }
 
 // Verify the default starter code applies exactly -12 dB of gain.
@@ -266,11 +391,9 @@ fn make_buffer(value: f64, channels: usize) -> Vec<Vec<f64>> {
 #[test]
 fn test_gain_minus_12db() {
     let expected = 10.0_f64.powf(-12.0 / 20.0);
-    let input = make_buffer(1.0, 2);
-    let mut output = make_buffer(0.0, 2);
-    rust_process(&input, &mut output);
+    let output = render(&make_buffer(1.0, 2));
 
-    for (ch, channel) in output.iter().enumerate() {
+    for (ch, channel) in output.channels().enumerate() {
         for (i, &sample) in channel.iter().enumerate() {
             let err = (sample - expected).abs();
             assert!(
@@ -284,11 +407,9 @@ fn test_gain_minus_12db() {
 // Zero input must produce zero output for any linear DSP.
 #[test]
 fn test_silence_in_silence_out() {
-    let input = make_buffer(0.0, 2);
-    let mut output = make_buffer(0.0, 2);
-    rust_process(&input, &mut output);
+    let output = render(&make_buffer(0.0, 2));
 
-    for (ch, channel) in output.iter().enumerate() {
+    for (ch, channel) in output.channels().enumerate() {
         for (i, &sample) in channel.iter().enumerate() {
             assert!(
                 sample.abs() < TOLERANCE,
@@ -302,30 +423,37 @@ fn test_silence_in_silence_out() {
 #[test]
 fn test_buffer_dimensions_preserved() {
     let input = make_buffer(0.5, 2);
-    let mut output = make_buffer(0.0, 2);
-    rust_process(&input, &mut output);
+    let output = render(&input);
 
-    assert_eq!(output.len(), input.len(), "channel count changed");
-    for ch in 0..output.len() {
-        assert_eq!(output[ch].len(), input[ch].len(), "sample count changed in ch{ch}");
-    }
+    assert_eq!(output.num_channels(), input.num_channels(), "channel count changed");
+    assert_eq!(output.num_samples(), input.num_samples(), "sample count changed");
 }
+"#;
+
+    let preset_file_content = r#"# A preset: values for parameters the DSP code declares, one `name = value` per line.
+# Render with it using `playdsp --preset example`, or with every preset in this
+# folder using `playdsp --preset all`. Parameters left out keep their default, and
+# --param values on the command line override the ones here.
+gain_db = -6.0
 "#;
 
     let rust_file_path = rust_dir.join("rust_process_audio.rs");
     let cpp_file_path = cpp_dir.join("cpp_process_audio.cpp");
     let tests_file_path = tests_dir.join("rust_tests.rs");
     let cpp_tests_file_path = tests_dir.join("cpp_tests.rs");
+    let preset_file_path = presets_dir.join("example.toml");
 
     write(&rust_file_path, rust_file_content).expect("Failed to write Rust file");
     write(&cpp_file_path, cpp_file_content).expect("Failed to write C++ file");
     write(&tests_file_path, tests_file_content).expect("Failed to write DSP tests file");
     write(&cpp_tests_file_path, cpp_tests_file_content).expect("Failed to write C++ tests file");
+    write(&preset_file_path, preset_file_content).expect("Failed to write example preset");
 
-    println!("Created folder structure with rust/, cpp/, and tests/ subdirectories");
+    println!("Created folder structure with rust/, cpp/, tests/ and presets/ subdirectories");
     println!("Rust processing files: {}", rust_dir.display());
     println!("C++ processing files: {}", cpp_dir.display());
     println!("DSP test files: {}", tests_dir.display());
+    println!("Presets: {}", presets_dir.display());
     println!("Place your audio files in: {}", source_dir.display());
     println!("Run 'playdsp test' to execute your DSP tests");
 }
@@ -0,0 +1,115 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// SHA-256 over every file in a DSP code folder, as compiled into the runtime. Files are
+// taken in order of their '/'-separated path below the folder, and each contributes its
+// path, a NUL, its length (u64, little-endian) and its contents, so renaming or moving a
+// file changes the hash as well.
+pub(crate) fn hash_program_sources(folder: &Path) -> io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(folder, "", &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for relative_path in files {
+        let contents = fs::read(folder.join(&relative_path))?;
+        hasher.update(relative_path.as_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative_path = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", relative_path), files)?;
+        } else {
+            files.push(relative_path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A scratch folder holding `files` (relative path, contents), created in the order given.
+    fn folder(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("playdsp_sources_{}_{}", std::process::id(), name));
+        if folder.exists() {
+            fs::remove_dir_all(&folder).unwrap();
+        }
+        for (path, contents) in files {
+            let path = folder.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        folder
+    }
+
+    fn hash(name: &str, files: &[(&str, &str)]) -> String {
+        let folder = folder(name, files);
+        let hash = hash_program_sources(&folder).unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+        hash
+    }
+
+    const SOURCES: [(&str, &str); 3] = [
+        ("rust/rust_process_audio.rs", "pub fn rust_process() {}"),
+        ("rust/filters.rs", "pub struct Biquad;"),
+        (
+            "cpp/cpp_process_audio.cpp",
+            "extern \"C\" void cpp_process();",
+        ),
+    ];
+
+    #[test]
+    fn hash_ignores_listing_order() {
+        let forward = hash("forward", &SOURCES);
+        let mut reversed = SOURCES;
+        reversed.reverse();
+        assert_eq!(forward, hash("reversed", &reversed));
+        assert_eq!(forward.len(), 64);
+    }
+
+    #[test]
+    fn hash_changes_with_renames_and_edits() {
+        let original = hash("original", &SOURCES);
+
+        let mut renamed = SOURCES;
+        renamed[1].0 = "rust/filter.rs";
+        assert_ne!(original, hash("renamed", &renamed));
+
+        let mut moved = SOURCES;
+        moved[1].0 = "rust/dsp/filters.rs";
+        assert_ne!(original, hash("moved", &moved));
+
+        let mut edited = SOURCES;
+        edited[1].1 = "pub struct Biquad; ";
+        assert_ne!(original, hash("edited", &edited));
+
+        // Moving bytes from the end of one file to the start of the next is an edit too.
+        let mut shifted = SOURCES;
+        shifted[0].1 = "extern \"C\" void cpp_process();pub";
+        shifted[1].1 = " struct Biquad;";
+        let mut split = SOURCES;
+        split[0].1 = "extern \"C\" void cpp_process();";
+        split[1].1 = "pub struct Biquad;";
+        assert_ne!(hash("shifted", &shifted), hash("split", &split));
+    }
+}
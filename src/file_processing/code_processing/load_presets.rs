@@ -0,0 +1,140 @@
+use crate::constants::constants::*;
+use crate::signal_processing::render_options::{check_param_name, parse_param_value};
+use std::fs;
+use std::path::Path;
+
+// A named set of parameter values, from `audio/processing/presets/<name>.toml`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Preset {
+    pub(crate) name: String,
+    pub(crate) params: Vec<(String, String)>,
+}
+
+// Loads the preset called `selection`, or every preset in the folder, in name order,
+// for `all`.
+pub(crate) fn load_presets(selection: &str) -> Result<Vec<Preset>, String> {
+    let folder = PRESETS_FOLDER.as_path();
+    let available = list_presets(folder)?;
+
+    if selection == ALL_PRESETS {
+        if available.is_empty() {
+            return Err(format!("No presets found in {}", folder.display()));
+        }
+        return available
+            .iter()
+            .map(|name| load_preset(folder, name))
+            .collect();
+    }
+
+    if !available.iter().any(|name| name == selection) {
+        return Err(if available.is_empty() {
+            format!(
+                "Preset '{}' not found: {} has no presets",
+                selection,
+                folder.display()
+            )
+        } else {
+            format!(
+                "Preset '{}' not found in {}: expected one of {}",
+                selection,
+                folder.display(),
+                available.join(", ")
+            )
+        });
+    }
+    Ok(vec![load_preset(folder, selection)?])
+}
+
+// Names of the `.toml` files in `folder`, sorted.
+fn list_presets(folder: &Path) -> Result<Vec<String>, String> {
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+    let entries =
+        fs::read_dir(folder).map_err(|e| format!("Error reading {}: {}", folder.display(), e))?;
+
+    let mut names: Vec<String> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("toml")
+        })
+        .filter_map(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(str::to_string)
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn load_preset(folder: &Path, name: &str) -> Result<Preset, String> {
+    let path = folder.join(format!("{}.toml", name));
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    let params = parse_preset(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(Preset {
+        name: name.to_string(),
+        params,
+    })
+}
+
+// A preset has one `name = value` line per parameter; values are numbers and `#` starts
+// a comment.
+fn parse_preset(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut params: Vec<(String, String)> = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected <name> = <value>", index + 1))?;
+        let (name, value) = (name.trim(), value.trim());
+        check_param_name(name)
+            .and_then(|_| parse_param_value(value))
+            .map_err(|e| format!("line {}: {}", index + 1, e))?;
+        if params.iter().any(|(given, _)| given == name) {
+            return Err(format!(
+                "line {}: {} is given more than once",
+                index + 1,
+                name
+            ));
+        }
+        params.push((name.to_string(), value.to_string()));
+    }
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_presets() {
+        let preset = "# Warm tape\ndrive = 0.8\n\n  cutoff=4500 # rolled off\nmix = -1e-2\n";
+        assert_eq!(
+            parse_preset(preset),
+            Ok(vec![
+                ("drive".to_string(), "0.8".to_string()),
+                ("cutoff".to_string(), "4500".to_string()),
+                ("mix".to_string(), "-1e-2".to_string()),
+            ])
+        );
+        assert_eq!(parse_preset(""), Ok(Vec::new()));
+        assert_eq!(parse_preset("# nothing set\n"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        let error = |content: &str| parse_preset(content).unwrap_err();
+        assert!(error("drive = 0.8\ncutoff 4500").starts_with("line 2:"));
+        assert!(error("drive = warm").starts_with("line 1:"));
+        assert!(error("\ndrive level = 0.8").starts_with("line 2:"));
+        assert!(error("drive = 0.8\n\ndrive = 0.9").starts_with("line 3: drive is given"));
+        assert!(error("[section]").starts_with("line 1:"));
+        assert!(error("name = \"warm\"").starts_with("line 1:"));
+    }
+}
@@ -1,3 +1,5 @@
 pub(crate) mod create_folders_and_copy_files;
 pub(crate) mod get_program_files;
+pub(crate) mod hash_program_sources;
+pub(crate) mod load_presets;
 pub(crate) mod process_and_copy_files;
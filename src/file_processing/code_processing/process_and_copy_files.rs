@@ -1,4 +1,5 @@
 use crate::constants::constants::*;
+use crate::program_recompile::run_recompile::{registers_processor, strip_comments_and_strings};
 use std::fs::copy;
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -75,20 +76,35 @@ fn check_cpp_function_signature(file_path: &str) -> io::Result<bool> {
     let mut file = fs::File::open(file_path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
-    let normalized: String = contents.split_whitespace().collect::<Vec<_>>().join(" ");
-    Ok(normalized.contains(
-        "extern \"C\" void cpp_process ( const double * input , size_t num_channels , size_t num_samples , double * output )"
-    ))
+    // Compare with all whitespace removed so formatting doesn't matter. The global and
+    // handle-based forms of both cpp_process and cpp_process_planar are accepted.
+    let compact: String = contents.split_whitespace().collect();
+    Ok([
+        "extern\"C\"voidcpp_process(constdouble*input,size_tnum_channels,size_tnum_samples,double*output)",
+        "extern\"C\"voidcpp_process(void*handle,constdouble*input,size_tnum_channels,size_tnum_samples,double*output)",
+        "extern\"C\"voidcpp_process_planar(constdouble*const*input,double*const*output,size_tnum_channels,size_tnum_samples)",
+        "extern\"C\"voidcpp_process_planar(void*handle,constdouble*const*input,double*const*output,size_tnum_channels,size_tnum_samples)",
+    ]
+    .iter()
+    .any(|signature| compact.contains(signature)))
 }
 
 fn check_rust_function_signature(file_path: &str) -> io::Result<bool> {
     let mut file = fs::File::open(file_path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
-    let normalized: String = contents.split_whitespace().collect::<Vec<_>>().join(" ");
-    Ok(normalized.contains(
-        "pub fn rust_process ( input : & Vec < Vec < f64 > > , output : & mut Vec < Vec < f64 > > )"
-    ))
+    Ok(has_rust_entry_point(&contents))
+}
+
+// Whether Rust source defines the free rust_process function or registers an
+// AudioProcessor with the macro. Comments and literals are ignored, as they are when the
+// runtime picks which of the two to call.
+pub(crate) fn has_rust_entry_point(source: &str) -> bool {
+    let code = strip_comments_and_strings(source);
+    // Compare with all whitespace removed so formatting doesn't matter.
+    let compact: String = code.split_whitespace().collect();
+    compact.contains("pubfnrust_process(input:&Vec<Vec<f64>>,output:&mutVec<Vec<f64>>)")
+        || registers_processor(&code)
 }
 
 fn copy_to_processing_folder(file_path: &str) -> io::Result<()> {
@@ -1,11 +1,16 @@
 mod file_processing;
 
 use file_processing::audio_processing::get_audio_files_from_folder::*;
+use file_processing::audio_processing::group_multi_mono::*;
+use file_processing::audio_processing::read_provenance::*;
 use file_processing::audio_processing::replace_audio_files::*;
 use file_processing::code_processing::create_folders_and_copy_files::*;
 use file_processing::code_processing::get_program_files::*;
 use file_processing::code_processing::process_and_copy_files::*;
+use signal_processing::parameter_sweep::*;
 use signal_processing::process_multiple_audio_files::*;
+use signal_processing::render_options::*;
+use signal_processing::verify_block_sizes::*;
 mod constants;
 mod program_recompile;
 mod signal_processing;
@@ -13,7 +18,7 @@ mod signal_processing;
 use program_recompile::run_recompile::*;
 use program_recompile::run_tests::*;
 
-use clap::{Arg, ArgAction, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use constants::constants::*;
 
 fn check_cpp_files_recursive(dir: &std::path::Path) -> bool {
@@ -35,6 +40,28 @@ fn check_cpp_files_recursive(dir: &std::path::Path) -> bool {
     false
 }
 
+fn param_arg() -> Arg {
+    Arg::new(PARAM_NAME)
+        .long("param")
+        .help("Value for a parameter the DSP code declares, e.g. --param cutoff=1200; repeat for several")
+        .required(false)
+        .num_args(1)
+        .value_name("name=value")
+        .value_parser(parse_param)
+        .action(ArgAction::Append)
+}
+
+fn buffer_size_arg() -> Arg {
+    Arg::new(BUFFER_SIZE_NAME)
+        .short('b')
+        .long("buffer-size")
+        .help("Number of samples per buffer passed to the DSP code (default: 1024)")
+        .required(false)
+        .num_args(1)
+        .value_parser(clap::value_parser!(u32).range(1..))
+        .action(ArgAction::Set)
+}
+
 fn main() {
     rayon::ThreadPoolBuilder::new()
         .num_threads(
@@ -79,6 +106,84 @@ fn main() {
                     .num_args(0)
                     .action(ArgAction::SetTrue)
                     .help("Run only C++ DSP tests (files prefixed with cpp_)"))
+                .arg(buffer_size_arg())
+        )
+        .subcommand(
+            Command::new("verify-blocksize")
+                .about("Render every source file at several block sizes and check that the outputs match")
+                .arg(Arg::new("rust")
+                    .short('r')
+                    .long("rust")
+                    .required(false)
+                    .num_args(0)
+                    .action(ArgAction::SetTrue)
+                    .help("Verify only the Rust code"))
+                .arg(Arg::new("cpp")
+                    .short('c')
+                    .long("cpp")
+                    .required(false)
+                    .num_args(0)
+                    .action(ArgAction::SetTrue)
+                    .help("Verify only the C++ code"))
+                .arg(Arg::new(SIZES_NAME)
+                    .short('s')
+                    .long("sizes")
+                    .help("Comma-separated block sizes to compare; `random` or `random:<min>-<max>` for varying sizes (default: 1,64,1024,random)")
+                    .required(false)
+                    .num_args(1)
+                    .value_parser(parse_verify_sizes)
+                    .action(ArgAction::Set))
+                .arg(Arg::new(TOLERANCE_NAME)
+                    .short('t')
+                    .long("tolerance")
+                    .help("Largest allowed per-sample difference between renders (default: 1e-9)")
+                    .required(false)
+                    .num_args(1)
+                    .value_parser(parse_tolerance)
+                    .action(ArgAction::Set))
+                .arg(Arg::new(SEED_NAME)
+                    .long("seed")
+                    .help("Seed for the random block sizes, to reproduce an earlier check")
+                    .required(false)
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(u64))
+                    .action(ArgAction::Set))
+                .arg(param_arg())
+        )
+        .subcommand(
+            Command::new("sweep")
+                .about("Render every source file at every combination of a grid of parameter values")
+                .arg(Arg::new("rust")
+                    .short('r')
+                    .long("rust")
+                    .required(false)
+                    .num_args(0)
+                    .action(ArgAction::SetTrue)
+                    .help("Sweep only the Rust code"))
+                .arg(Arg::new("cpp")
+                    .short('c')
+                    .long("cpp")
+                    .required(false)
+                    .num_args(0)
+                    .action(ArgAction::SetTrue)
+                    .help("Sweep only the C++ code"))
+                .arg(Arg::new(PARAM_NAME)
+                    .long("param")
+                    .help("Values to sweep a parameter over: name=<start>..<end>:<lin|log>:<steps> or name=<v1>,<v2>,...; repeat for several")
+                    .required(true)
+                    .num_args(1)
+                    .value_name("name=values")
+                    .value_parser(parse_sweep_param)
+                    .action(ArgAction::Append))
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Show the provenance recorded in a rendered file by --provenance")
+                .arg(Arg::new(INFO_FILE_NAME)
+                    .help("Rendered WAV or FLAC file")
+                    .required(true)
+                    .num_args(1)
+                    .action(ArgAction::Set))
         )
         .arg(Arg::new("rust")
             .short('r')
@@ -104,17 +209,147 @@ fn main() {
         .arg(Arg::new(AUDIO_FILE_PATH_NAME)
             .short('a')
             .long("audio")
-            .help("Optional folder path containing .wav files")
+            .help("Optional folder path containing .wav, .flac, .aif/.aiff/.aifc, .mp3 or .ogg/.opus files")
             .required(false)
             .num_args(1)
             .action(ArgAction::Set))
-        .arg(Arg::new("meta")
+        .arg(Arg::new(META_NAME)
             .short('m')
             .long("meta")
             .required(false)
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("all")
+            .value_name("chunks")
+            .value_parser(parse_meta_chunks)
+            .action(ArgAction::Set)
+            .help("Preserve metadata from input files in output: bext, LIST/INFO tags, cue markers and regions, and iXML. --meta=<list> keeps only some of them, e.g. --meta=bext,markers"))
+        .arg(Arg::new(PROVENANCE_NAME)
+            .long("provenance")
+            .required(false)
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("ixml")
+            .value_name("target")
+            .value_parser(["ixml", "bext"])
+            .action(ArgAction::Set)
+            .help("Record how each file was rendered (language, source hash, playdsp version, block sizes, input file hash) in its iXML chunk or FLAC comments; --provenance=bext also sums it up in the bext description"))
+        .arg(param_arg())
+        .arg(Arg::new(PRESET_NAME)
+            .long("preset")
+            .help("Render with the parameter values in audio/processing/presets/<name>.toml, or once with each preset for `all`; --param values override the preset's")
+            .required(false)
+            .num_args(1)
+            .value_name("name|all")
+            .action(ArgAction::Set))
+        .arg(Arg::new(AUTOMATION_NAME)
+            .long("automation")
+            .help("CSV or JSON file of time_seconds,param,value[,curve] breakpoints that move parameters during the render")
+            .required(false)
+            .num_args(1)
+            .value_name("file")
+            .action(ArgAction::Set))
+        .arg(Arg::new(AUTOMATION_RATE_NAME)
+            .long("automation-rate")
+            .help("Pass automated values to the DSP code at every sample (default) or once per block")
+            .required(false)
+            .num_args(1)
+            .value_parser(["sample", "block"])
+            .requires(AUTOMATION_NAME)
+            .action(ArgAction::Set))
+        .arg(Arg::new(MIDI_NAME)
+            .long("midi")
+            .help("Standard MIDI File whose note, CC and pitch-bend events are sent to the DSP code with every source; without it, a <source name>.mid next to a source is used")
+            .required(false)
+            .num_args(1)
+            .value_name("file")
+            .action(ArgAction::Set))
+        .arg(buffer_size_arg())
+        .arg(Arg::new(BLOCK_SIZES_NAME)
+            .long("block-sizes")
+            .help("Vary the block size between calls: random:<min>-<max> or list:<n>,<n>,...")
+            .required(false)
+            .num_args(1)
+            .value_parser(parse_block_sizes)
+            .conflicts_with(BUFFER_SIZE_NAME)
+            .action(ArgAction::Set))
+        .arg(Arg::new(SEED_NAME)
+            .long("seed")
+            .help("Seed for --block-sizes random:<min>-<max>, to reproduce an earlier render")
+            .required(false)
+            .num_args(1)
+            .value_parser(clap::value_parser!(u64))
+            .action(ArgAction::Set))
+        .arg(Arg::new(OUTPUT_FORMAT_NAME)
+            .long("output-format")
+            .help("Sample format of the rendered files (default: float32 for WAV, pcm24 for FLAC)")
+            .required(false)
+            .num_args(1)
+            .value_parser(OUTPUT_FORMATS.to_vec())
+            .action(ArgAction::Set))
+        .arg(Arg::new(OUTPUT_CONTAINER_NAME)
+            .long("output-container")
+            .help("File type of the rendered files; FLAC is lossless at 16 or 24 bit (default: wav)")
+            .required(false)
+            .num_args(1)
+            .value_parser(["wav", "flac"])
+            .action(ArgAction::Set))
+        .arg(Arg::new(DITHER_NAME)
+            .long("dither")
+            .help("Dither added when quantizing to an integer format (default: tpdf)")
+            .required(false)
+            .num_args(1)
+            .value_parser(["tpdf", "none"])
+            .action(ArgAction::Set))
+        .arg(Arg::new(NOISE_SHAPING_NAME)
+            .long("noise-shaping")
+            .help("Noise shaping applied when quantizing to an integer format (default: none)")
+            .required(false)
+            .num_args(1)
+            .value_parser(["none", "first-order", "second-order"])
+            .action(ArgAction::Set))
+        .arg(Arg::new(DITHER_SEED_NAME)
+            .long("dither-seed")
+            .help("Seed for the dither noise; the same seed gives identical files (default: 0)")
+            .required(false)
+            .num_args(1)
+            .value_parser(clap::value_parser!(u64))
+            .action(ArgAction::Set))
+        .arg(Arg::new(SAMPLE_RATE_NAME)
+            .long("sample-rate")
+            .help("Resample every file to this rate (Hz) before processing")
+            .required(false)
+            .num_args(1)
+            .value_parser(clap::value_parser!(u32).range(1..))
+            .action(ArgAction::Set))
+        .arg(Arg::new(RESTORE_RATE_NAME)
+            .long("restore-rate")
+            .required(false)
             .num_args(0)
             .action(ArgAction::SetTrue)
-            .help("Preserve BWF metadata (bext chunk) from input WAV files in output"))
+            .help("Convert the rendered files back to the sample rate of their source"))
+        .arg(Arg::new(RATE_MATRIX_NAME)
+            .long("rate-matrix")
+            .help("Render every file once at each of these rates, e.g. 44100,48000,96000")
+            .required(false)
+            .num_args(1)
+            .value_parser(parse_rate_matrix)
+            .conflicts_with(SAMPLE_RATE_NAME)
+            .action(ArgAction::Set))
+        .arg(Arg::new(MULTI_MONO_NAME)
+            .long("multi-mono")
+            .help("Merge multi-mono siblings into one multichannel input: pro-tools (take.L.wav, take.R.wav, ...), numbered (take_1.wav, take_2.wav, ...) or suffixes:<s1>,<s2>,...; repeat to try several rules")
+            .required(false)
+            .num_args(1)
+            .value_parser(parse_grouping_rule)
+            .action(ArgAction::Append))
+        .arg(Arg::new(SPLIT_MULTI_MONO_NAME)
+            .long("split-multi-mono")
+            .required(false)
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .requires(MULTI_MONO_NAME)
+            .help("Write each merged render back out as one file per source file"))
         .get_matches();
 
     if let Some(sub_matches) = matches.subcommand_matches("new") {
@@ -127,13 +362,158 @@ fn main() {
     if let Some(test_matches) = matches.subcommand_matches("test") {
         let rust_only = test_matches.get_flag("rust");
         let cpp_only = test_matches.get_flag("cpp");
-        run_tests(rust_only, cpp_only);
+        let buffer_size = test_matches
+            .get_one::<u32>(BUFFER_SIZE_NAME)
+            .copied()
+            .unwrap_or(DEFAULT_BUFFER_SIZE);
+        run_tests(rust_only, cpp_only, buffer_size);
+        return;
+    }
+
+    if let Some(info_matches) = matches.subcommand_matches("info") {
+        let file = info_matches
+            .get_one::<String>(INFO_FILE_NAME)
+            .map(String::as_str)
+            .unwrap_or("");
+        match read_provenance(std::path::Path::new(file)) {
+            Ok(provenance) if !provenance.fields.is_empty() => {
+                println!("{}", file);
+                for (field, value) in &provenance.fields {
+                    println!("  {:<17} {}", field, value);
+                }
+                if let Some(description) = &provenance.description {
+                    println!("  {:<17} {}", "DESCRIPTION", description);
+                }
+            }
+            Ok(_) => {
+                eprintln!("{}: no playdsp provenance found", file);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error reading {}: {}", file, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(verify_matches) = matches.subcommand_matches("verify-blocksize") {
+        let rust_only = verify_matches.get_flag("rust");
+        let cpp_only = verify_matches.get_flag("cpp");
+        let sizes = verify_matches
+            .get_one::<String>(SIZES_NAME)
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_VERIFY_SIZES);
+        let tolerance = verify_matches
+            .get_one::<f64>(TOLERANCE_NAME)
+            .copied()
+            .unwrap_or(DEFAULT_TOLERANCE);
+        let mut render_options = match RenderOptions::from_matches(&matches) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        // The check chooses the block sizes itself and writes no files.
+        render_options.block_sizes = None;
+        render_options.provenance = None;
+        if let Some(&seed) = verify_matches.get_one::<u64>(SEED_NAME) {
+            render_options.seed = seed;
+        }
+        let params = match collect_params(verify_matches) {
+            Ok(params) => params,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if let Some((name, _)) = params
+            .iter()
+            .find(|(name, _)| render_options.params.iter().any(|(given, _)| given == name))
+        {
+            eprintln!(
+                "Error: --param {} is given both before and after verify-blocksize",
+                name
+            );
+            std::process::exit(1);
+        }
+        render_options.params.extend(params);
+
+        ensure_runtime_compiled(&matches);
+
+        println!(
+            "Verifying block sizes {} (seed {}, tolerance {:e})",
+            sizes, render_options.seed, tolerance
+        );
+        let audio_files_to_process = group_multi_mono(
+            get_audio_files_from_folder(SOURCE_NAME),
+            &render_options.multi_mono,
+        );
+        let program_files = select_program_files(rust_only, cpp_only);
+        if !verify_block_sizes(
+            &audio_files_to_process,
+            &program_files,
+            &render_options,
+            sizes,
+            tolerance,
+        ) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(sweep_matches) = matches.subcommand_matches("sweep") {
+        let rust_only = sweep_matches.get_flag("rust");
+        let cpp_only = sweep_matches.get_flag("cpp");
+        let axes: Vec<SweepAxis> = sweep_matches
+            .get_many::<SweepAxis>(PARAM_NAME)
+            .map(|axes| axes.cloned().collect())
+            .unwrap_or_default();
+        let render_options = match RenderOptions::from_matches(&matches) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let points = match sweep_points(&axes, &render_options) {
+            Ok(points) => points,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        ensure_runtime_compiled(&matches);
+
+        let audio_files_to_process = group_multi_mono(
+            get_audio_files_from_folder(SOURCE_NAME),
+            &render_options.multi_mono,
+        );
+        let program_files = select_program_files(rust_only, cpp_only);
+        if let Err(e) = run_sweep(
+            &audio_files_to_process,
+            &program_files,
+            &render_options,
+            &axes,
+            &points,
+        ) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
         return;
     }
 
     let rust_present = matches.contains_id("rust");
     let cpp_present = matches.contains_id("cpp");
-    let preserve_meta = matches.get_flag("meta");
+    let render_options = match RenderOptions::from_matches(&matches) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
 
     if let Some(folder_path) = matches.get_one::<String>(CODE_FILE_PATH_NAME) {
         if rust_present && !cpp_present {
@@ -162,6 +542,56 @@ fn main() {
         }
     }
 
+    ensure_runtime_compiled(&matches);
+
+    if !rust_present && !cpp_present {
+        println!("Processing with both Rust and C++ code");
+    } else if rust_present {
+        println!("Processing with Rust code");
+    } else if cpp_present {
+        println!("Processing with C++ code");
+    }
+
+    if let Some(spec) = &render_options.block_sizes {
+        if render_options.uses_random_block_sizes() {
+            println!(
+                "Block sizes: {} (seed {}, pass --seed {} to reproduce)",
+                spec, render_options.seed, render_options.seed
+            );
+        } else {
+            println!("Block sizes: {}", spec);
+        }
+    }
+
+    if !render_options.presets.is_empty() {
+        let names: Vec<&str> = render_options
+            .presets
+            .iter()
+            .map(|preset| preset.name.as_str())
+            .collect();
+        println!("Presets: {}", names.join(", "));
+    }
+
+    let audio_files_to_process = group_multi_mono(
+        get_audio_files_from_folder(SOURCE_NAME),
+        &render_options.multi_mono,
+    );
+    for group in audio_files_to_process.iter().filter(|g| g.is_multi_mono()) {
+        println!("Multi-mono: {} ({})", group.name, group.suffixes.join(", "));
+    }
+    for group in &audio_files_to_process {
+        if let Some(midi) = render_options.midi_file(group) {
+            println!("MIDI: {} ({})", group.name, midi);
+        }
+    }
+
+    let program_files = select_program_files(rust_present, cpp_present);
+    process_multiple_audio_files(&audio_files_to_process, &program_files, &render_options);
+}
+
+// Recompiles the runtime when DSP code is present, or builds it from the default code
+// when no runtime binary exists yet.
+fn ensure_runtime_compiled(matches: &ArgMatches) {
     let runtime_binary = std::path::PathBuf::from("../audio/.playdsp_runtime/target/release")
         .join(format!("playdsp_runtime{}", std::env::consts::EXE_SUFFIX));
 
@@ -174,42 +604,28 @@ fn main() {
 
     if has_rust_files || has_cpp_files || has_dependencies_toml {
         println!("DSP code detected - recompiling runtime to ensure latest changes...");
-        run_recompile(&matches);
+        run_recompile(matches);
     } else if !runtime_binary.exists() {
         println!("Runtime binary not found. Compiling runtime with default code...");
-        run_recompile(&matches);
-    }
-
-    if !rust_present && !cpp_present {
-        println!("Processing with both Rust and C++ code");
-    } else if rust_present {
-        println!("Processing with Rust code");
-    } else if cpp_present {
-        println!("Processing with C++ code");
+        run_recompile(matches);
     }
+}
 
-    let audio_files_to_process = get_audio_files_from_folder(SOURCE_NAME);
-
-    let mut rust_files: Vec<String> = vec![];
-    let mut cpp_files: Vec<String> = vec![];
+fn select_program_files(rust_present: bool, cpp_present: bool) -> Vec<String> {
+    let mut program_files: Vec<String> = vec![];
 
-    if !rust_present && !cpp_present {
-        rust_files = get_program_files(RUST_FOLDER.to_str().unwrap_or(""), "rs");
-        cpp_files = get_program_files(CPP_FOLDER.to_str().unwrap_or(""), "cpp");
-    } else if rust_present {
-        rust_files = get_program_files(RUST_FOLDER.to_str().unwrap_or(""), "rs");
-    } else if cpp_present {
-        cpp_files = get_program_files(CPP_FOLDER.to_str().unwrap_or(""), "cpp");
+    if rust_present || !cpp_present {
+        program_files.append(&mut get_program_files(
+            RUST_FOLDER.to_str().unwrap_or(""),
+            "rs",
+        ));
     }
-
-    if !rust_present && !cpp_present {
-        let mut all_files = Vec::new();
-        all_files.append(rust_files.as_mut());
-        all_files.append(cpp_files.as_mut());
-        process_multiple_audio_files(&audio_files_to_process, &all_files, preserve_meta);
-    } else if rust_present {
-        process_multiple_audio_files(&audio_files_to_process, &rust_files, preserve_meta);
-    } else if cpp_present {
-        process_multiple_audio_files(&audio_files_to_process, &cpp_files, preserve_meta);
+    if !rust_present {
+        program_files.append(&mut get_program_files(
+            CPP_FOLDER.to_str().unwrap_or(""),
+            "cpp",
+        ));
     }
+
+    program_files
 }
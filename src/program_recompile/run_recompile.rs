@@ -10,6 +10,11 @@ use std::{fs, io};
 const CARGO_TOML_TEMPLATE: &str = include_str!("../../templates/Cargo.toml.template");
 const BUILD_RS_TEMPLATE: &str = include_str!("../../templates/build.rs.template");
 const MAIN_RS_TEMPLATE: &str = include_str!("../../templates/main.rs.template");
+const PROCESSOR_RS_TEMPLATE: &str = include_str!("../../templates/processor.rs.template");
+const AUDIO_IO_RS_TEMPLATE: &str = include_str!("../../templates/audio_io.rs.template");
+const AUTOMATION_RS_TEMPLATE: &str = include_str!("../../templates/automation.rs.template");
+const MIDI_RS_TEMPLATE: &str = include_str!("../../templates/midi.rs.template");
+const RESAMPLE_RS_TEMPLATE: &str = include_str!("../../templates/resample.rs.template");
 
 pub(crate) fn run_recompile(_matches: &ArgMatches) {
     let audio_dir = Path::new("../audio");
@@ -67,6 +72,14 @@ pub(crate) fn setup_runtime_project(runtime_dir: &Path, processing_dir: &Path) -
 
     fs::write(runtime_dir.join("build.rs"), BUILD_RS_TEMPLATE)?;
     fs::write(runtime_dir.join("src/main.rs"), MAIN_RS_TEMPLATE)?;
+    fs::write(runtime_dir.join("src/processor.rs"), PROCESSOR_RS_TEMPLATE)?;
+    fs::write(runtime_dir.join("src/audio_io.rs"), AUDIO_IO_RS_TEMPLATE)?;
+    fs::write(
+        runtime_dir.join("src/automation.rs"),
+        AUTOMATION_RS_TEMPLATE,
+    )?;
+    fs::write(runtime_dir.join("src/midi.rs"), MIDI_RS_TEMPLATE)?;
+    fs::write(runtime_dir.join("src/resample.rs"), RESAMPLE_RS_TEMPLATE)?;
 
     Ok(())
 }
@@ -107,21 +120,35 @@ pub(crate) fn inject_user_rust_code(runtime_dir: &Path, processing_dir: &Path) -
 
             mod_declarations.sort();
             let mut mod_rs_content = mod_declarations.join("\n");
-            mod_rs_content.push_str("\n\npub use rust_process_audio::rust_process;\n");
+
+            let user_code = fs::read_to_string(&rust_process_file)?;
+            let code = strip_comments_and_strings(&user_code);
+            let defines_rust_process = defines_pub_fn(&code, "rust_process");
+            if defines_rust_process {
+                mod_rs_content.push_str("\n\npub use rust_process_audio::rust_process;\n");
+            } else {
+                mod_rs_content.push('\n');
+            }
 
             fs::write(runtime_user_code_dir.join("mod.rs"), mod_rs_content)?;
 
-            let start_marker =
-                "// Rust processing function - will be loaded from user's code\nfn rust_process";
+            let start_marker = "// Rust processing function - will be loaded from user's code\n#[allow(dead_code)]\nfn rust_process";
             let end_marker = "\n}\n\n// C++ FFI";
 
             if let Some(start_idx) = main_rs_content.find(start_marker) {
                 if let Some(end_idx) = main_rs_content[start_idx..].find(end_marker) {
-                    let actual_end = start_idx + end_idx + 2;
-                    main_rs_content.replace_range(
-                        start_idx..actual_end,
-                        "// Rust processing function - loaded from user's code module\nmod user_code;\n\nfn rust_process(input: &Vec<Vec<f64>>, output: &mut Vec<Vec<f64>>) {\n    user_code::rust_process(input, output);\n}"
-                    );
+                    if defines_rust_process {
+                        let actual_end = start_idx + end_idx + 2;
+                        main_rs_content.replace_range(
+                            start_idx..actual_end,
+                            "// Rust processing function - loaded from user's code module\nmod user_code;\n\nfn rust_process(input: &Vec<Vec<f64>>, output: &mut Vec<Vec<f64>>) {\n    user_code::rust_process(input, output);\n}"
+                        );
+                    } else {
+                        main_rs_content.insert_str(start_idx, "mod user_code;\n\n");
+                    }
+
+                    inject_rust_lifecycle_hooks(&mut main_rs_content, &code);
+                    inject_rust_processor(&mut main_rs_content, &code);
 
                     fs::write(&main_rs_path, main_rs_content)?;
                 }
@@ -131,6 +158,218 @@ pub(crate) fn inject_user_rust_code(runtime_dir: &Path, processing_dir: &Path) -
     Ok(())
 }
 
+// Replaces the runtime's no-op lifecycle hooks with calls into the user's
+// rust_parameters / rust_set_parameter / rust_set_channel_layout / rust_prepare /
+// rust_reset / rust_release / rust_receive_midi when rust_process_audio.rs defines them.
+// `code` is the user's source with comments and literals stripped.
+fn inject_rust_lifecycle_hooks(main_rs_content: &mut String, code: &str) {
+    let hooks = [
+        (
+            "rust_parameters",
+            "fn rust_parameters() -> Vec<Parameter> {\n    Vec::new()\n}",
+            "fn rust_parameters() -> Vec<Parameter> {\n    user_code::rust_process_audio::rust_parameters()\n}",
+        ),
+        (
+            "rust_set_parameter",
+            "fn rust_set_parameter(_name: &str, _value: f64) {}",
+            "fn rust_set_parameter(name: &str, value: f64) {\n    user_code::rust_process_audio::rust_set_parameter(name, value);\n}",
+        ),
+        (
+            "rust_set_channel_layout",
+            "fn rust_set_channel_layout(_layout: &ChannelLayout) {}",
+            "fn rust_set_channel_layout(layout: &ChannelLayout) {\n    user_code::rust_process_audio::rust_set_channel_layout(layout);\n}",
+        ),
+        (
+            "rust_prepare",
+            "fn rust_prepare(_sample_rate: f64, _max_block: usize, _num_channels: usize) {}",
+            "fn rust_prepare(sample_rate: f64, max_block: usize, num_channels: usize) {\n    user_code::rust_process_audio::rust_prepare(sample_rate, max_block, num_channels);\n}",
+        ),
+        (
+            "rust_reset",
+            "fn rust_reset() {}",
+            "fn rust_reset() {\n    user_code::rust_process_audio::rust_reset();\n}",
+        ),
+        (
+            "rust_release",
+            "fn rust_release() {}",
+            "fn rust_release() {\n    user_code::rust_process_audio::rust_release();\n}",
+        ),
+        (
+            "rust_receive_midi",
+            "fn rust_receive_midi(_events: &[MidiEvent]) {}",
+            "fn rust_receive_midi(events: &[MidiEvent]) {\n    user_code::rust_process_audio::rust_receive_midi(events);\n}",
+        ),
+    ];
+
+    for (name, stub, call) in hooks {
+        if defines_pub_fn(code, name) {
+            *main_rs_content = main_rs_content.replace(stub, call);
+        }
+    }
+}
+
+// Points the runtime's processor factory at the user's AudioProcessor when
+// rust_process_audio.rs registers one with register_processor!.
+fn inject_rust_processor(main_rs_content: &mut String, code: &str) {
+    if registers_processor(code) {
+        *main_rs_content = main_rs_content.replace(
+            "fn create_rust_processor() -> Box<dyn AudioProcessor> {\n    Box::new(RustFunctionProcessor)\n}",
+            "fn create_rust_processor() -> Box<dyn AudioProcessor> {\n    user_code::rust_process_audio::create_processor()\n}",
+        );
+    }
+}
+
+// Whether stripped Rust source invokes `register_processor!(...)`.
+pub(crate) fn registers_processor(code: &str) -> bool {
+    tokens(code)
+        .windows(3)
+        .any(|window| window == ["register_processor", "!", "("])
+}
+
+// Whether stripped Rust source defines `pub fn <name>(...)` with a body, as opposed to a
+// declaration ending in `;`.
+fn defines_pub_fn(code: &str, name: &str) -> bool {
+    let tokens = tokens(code);
+    let signature = ["pub", "fn", name, "("];
+    tokens
+        .windows(signature.len())
+        .enumerate()
+        .any(|(start, window)| {
+            window == signature && body_follows(&tokens[start + signature.len() - 1..])
+        })
+}
+
+// Whether the parameter list opened by the first token is closed and, past any return
+// type, followed by `{` before a `;`.
+fn body_follows(tokens: &[&str]) -> bool {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match *token {
+            "(" => depth += 1,
+            ")" => {
+                depth -= 1;
+                if depth == 0 {
+                    return tokens[index + 1..]
+                        .iter()
+                        .find(|token| matches!(**token, "{" | ";"))
+                        == Some(&"{");
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+// Splits code into identifiers (or numbers) and single punctuation characters.
+fn tokens(code: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut chars = code.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        if is_identifier_char(c) {
+            while let Some(&(index, next)) = chars.peek() {
+                if !is_identifier_char(next) {
+                    break;
+                }
+                end = index + next.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(&code[start..end]);
+    }
+    tokens
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Replaces each comment and string or character literal in Rust source with a space.
+// Block comments nest, and raw strings end at a quote followed by as many `#` as opened
+// them. A quote that does not close within one character is a lifetime and is kept.
+pub(crate) fn strip_comments_and_strings(source: &str) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let mut code = String::with_capacity(source.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let next = chars.get(i + 1).copied();
+        let starts_token = i == 0 || !is_identifier_char(chars[i - 1]);
+        if let Some(hashes) = raw_string_hashes(&chars[i..]).filter(|_| starts_token) {
+            i += hashes + 2;
+            while i < chars.len()
+                && !(chars[i] == '"' && (1..=hashes).all(|k| chars.get(i + k) == Some(&'#')))
+            {
+                i += 1;
+            }
+            i += hashes + 1;
+            code.push(' ');
+            continue;
+        }
+        match chars[i] {
+            '/' if next == Some('/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                let mut depth = 0;
+                while i < chars.len() {
+                    if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                        depth += 1;
+                        i += 2;
+                    } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            '"' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            '\'' if next == Some('\\') || chars.get(i + 2) == Some(&'\'') => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            c => {
+                code.push(c);
+                i += 1;
+                continue;
+            }
+        }
+        code.push(' ');
+    }
+    code
+}
+
+// The number of `#` opening the raw string `chars` starts with, if it starts with one.
+fn raw_string_hashes(chars: &[char]) -> Option<usize> {
+    let rest = chars.strip_prefix(&['r'])?;
+    let hashes = rest.iter().take_while(|&&c| c == '#').count();
+    (rest.get(hashes) == Some(&'"')).then_some(hashes)
+}
+
 fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
     fs::create_dir_all(dst)?;
 
@@ -303,9 +542,14 @@ fn generate_cargo_toml_with_dependencies(dependencies: &HashMap<String, String>)
             if let Some(newline_idx) = cargo_toml[after_deps_header..].find('\n') {
                 let insert_pos = after_deps_header + newline_idx + 1;
 
+                // Crates the runtime already depends on keep the runtime's entry, as a
+                // second key for the same crate would make the manifest invalid.
+                let runtime_crates = template_dependency_names();
                 let mut dep_string = String::new();
                 for (name, version) in dependencies {
-                    dep_string.push_str(&format!("{} = {}\n", name, version));
+                    if !runtime_crates.contains(&name.as_str()) {
+                        dep_string.push_str(&format!("{} = {}\n", name, version));
+                    }
                 }
 
                 cargo_toml.insert_str(insert_pos, &dep_string);
@@ -315,3 +559,120 @@ fn generate_cargo_toml_with_dependencies(dependencies: &HashMap<String, String>)
 
     cargo_toml
 }
+
+// The crate names in the runtime template's [dependencies] section.
+fn template_dependency_names() -> Vec<&'static str> {
+    CARGO_TOML_TEMPLATE
+        .lines()
+        .skip_while(|line| line.trim() != "[dependencies]")
+        .skip(1)
+        .take_while(|line| !line.trim_start().starts_with('['))
+        .filter_map(|line| line.split_once('=').map(|(name, _)| name.trim()))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_processing::code_processing::process_and_copy_files::has_rust_entry_point;
+
+    fn defines(source: &str, name: &str) -> bool {
+        defines_pub_fn(&strip_comments_and_strings(source), name)
+    }
+
+    #[test]
+    fn finds_definitions_across_formatting() {
+        assert!(defines("pub fn rust_reset() {}", "rust_reset"));
+        assert!(defines("pub  fn\n  rust_reset(\n) {\n}", "rust_reset"));
+        assert!(defines(
+            "pub fn rust_parameters() -> Vec<Parameter> { vec![] }",
+            "rust_parameters"
+        ));
+        assert!(defines(
+            "pub fn rust_prepare(rate: f64, block: usize, channels: usize) where usize: Copy {}",
+            "rust_prepare"
+        ));
+    }
+
+    #[test]
+    fn ignores_comments_and_literals() {
+        assert!(!defines("// pub fn rust_reset() {}", "rust_reset"));
+        assert!(!defines(
+            "/* outer /* pub fn rust_reset() {} */ */",
+            "rust_reset"
+        ));
+        assert!(!defines(
+            r#"let s = "pub fn rust_reset() {}";"#,
+            "rust_reset"
+        ));
+        assert!(!defines(
+            r###"let s = r#"pub fn rust_reset() {} "quoted""#;"###,
+            "rust_reset"
+        ));
+        assert!(defines(
+            "fn f<'a>(x: &'a str) -> char { '\"' }\npub fn rust_reset() {}",
+            "rust_reset"
+        ));
+    }
+
+    #[test]
+    fn requires_whole_names_and_a_body() {
+        assert!(!defines("pub fn rust_reset_all() {}", "rust_reset"));
+        assert!(!defines("pub fn my_rust_reset() {}", "rust_reset"));
+        assert!(!defines("fn rust_reset() {}", "rust_reset"));
+        assert!(!defines("trait T { pub fn rust_reset(); }", "rust_reset"));
+        assert!(!defines("pub(crate) fn rust_reset() {}", "rust_reset"));
+    }
+
+    #[test]
+    fn finds_registered_processor() {
+        let mut main_rs = "fn create_rust_processor() -> Box<dyn AudioProcessor> {\n    Box::new(RustFunctionProcessor)\n}".to_string();
+        inject_rust_processor(
+            &mut main_rs,
+            &strip_comments_and_strings("// register_processor!(Old);"),
+        );
+        assert!(main_rs.contains("RustFunctionProcessor"));
+        inject_rust_processor(
+            &mut main_rs,
+            &strip_comments_and_strings("register_processor! (Gain);"),
+        );
+        assert!(main_rs.contains("user_code::rust_process_audio::create_processor()"));
+    }
+
+    #[test]
+    fn signature_check_ignores_commented_out_registration() {
+        let commented = "use crate::processor::AudioProcessor;\n// register_processor!(Gain);\n";
+        assert!(!has_rust_entry_point(commented));
+        assert!(!registers_processor(&strip_comments_and_strings(commented)));
+        assert!(!has_rust_entry_point(
+            r#"const DOC: &str = "register_processor!(Gain)";"#
+        ));
+        assert!(!has_rust_entry_point(
+            "/* pub fn rust_process(input: &Vec<Vec<f64>>, output: &mut Vec<Vec<f64>>) {} */"
+        ));
+
+        let registered = "crate::register_processor!(Gain { level: 1.0 });";
+        assert!(has_rust_entry_point(registered));
+        assert!(registers_processor(&strip_comments_and_strings(registered)));
+        assert!(has_rust_entry_point(
+            "pub fn rust_process(input: &Vec<Vec<f64>>,\n    output: &mut Vec<Vec<f64>>) {}"
+        ));
+    }
+
+    #[test]
+    fn user_dependencies_skip_runtime_crates() {
+        let names = template_dependency_names();
+        assert!(names.contains(&"serde_json"));
+        assert!(!names.contains(&"cc"));
+
+        let dependencies = HashMap::from([
+            ("serde".to_string(), "\"*\"".to_string()),
+            ("rand".to_string(), "\"0.8\"".to_string()),
+        ]);
+        let cargo_toml = generate_cargo_toml_with_dependencies(&dependencies);
+        assert!(cargo_toml.contains("rand = \"0.8\"\n"));
+        assert!(!cargo_toml.contains("serde = \"*\""));
+        assert_eq!(cargo_toml.matches("\nserde = ").count(), 1);
+    }
+}
@@ -5,7 +5,7 @@ use std::{fs, io};
 
 use super::run_recompile::{inject_user_rust_code, setup_runtime_project};
 
-pub(crate) fn run_tests(rust_only: bool, cpp_only: bool) {
+pub(crate) fn run_tests(rust_only: bool, cpp_only: bool, buffer_size: u32) {
     let audio_dir = Path::new("../audio");
     let runtime_dir = audio_dir.join(".playdsp_runtime");
     let processing_dir = &*PROGRAM_FOLDER;
@@ -27,9 +27,13 @@ pub(crate) fn run_tests(rust_only: bool, cpp_only: bool) {
         exit(1);
     }
 
+    // Only the tests in user_code: the runtime's own unit tests are run by playdsp's test
+    // suite.
     let status = Command::new("cargo")
         .arg("test")
+        .arg("user_code::")
         .current_dir(&runtime_dir)
+        .env("PLAYDSP_BUFFER_SIZE", buffer_size.to_string())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .status()
@@ -1 +1,4 @@
+pub(crate) mod parameter_sweep;
 pub(crate) mod process_multiple_audio_files;
+pub(crate) mod render_options;
+pub(crate) mod verify_block_sizes;
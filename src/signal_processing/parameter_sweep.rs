@@ -0,0 +1,342 @@
+use crate::constants::constants::*;
+use crate::file_processing::audio_processing::group_multi_mono::InputGroup;
+use crate::signal_processing::process_multiple_audio_files::{CompletedRender, render_files};
+use crate::signal_processing::render_options::{
+    ParamSet, RenderOptions, check_param_name, parse_param_value,
+};
+use chrono::Local;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// One swept parameter and the values it takes, as they appear in file names.
+#[derive(Clone, Debug)]
+pub(crate) struct SweepAxis {
+    pub(crate) name: String,
+    pub(crate) values: Vec<String>,
+}
+
+// Parses a sweep --param: `name=<start>..<end>:<lin|log>:<steps>` for evenly spaced
+// values (in value or in log value), or `name=<v1>,<v2>,...` for a list.
+pub(crate) fn parse_sweep_param(spec: &str) -> Result<SweepAxis, String> {
+    let (name, values) = spec.split_once('=').ok_or_else(|| {
+        format!(
+            "expected <name>=<start>..<end>:<lin|log>:<steps> or <name>=<v1>,<v2>,..., got '{}'",
+            spec
+        )
+    })?;
+    check_param_name(name)?;
+
+    let values = if let Some((start, rest)) = values.split_once("..") {
+        let mut parts = rest.split(':');
+        let (end, scale, steps) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(end), Some(scale), Some(steps), None) => (end, scale, steps),
+            _ => {
+                return Err(format!(
+                    "expected <start>..<end>:<lin|log>:<steps>, got '{}'",
+                    values
+                ));
+            }
+        };
+        let (start, end) = (parse_param_value(start)?, parse_param_value(end)?);
+        let steps = steps
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&steps| steps >= 2)
+            .ok_or_else(|| format!("'{}' is not a step count of 2 or more", steps))?;
+        range_values(start, end, scale, steps)?
+            .into_iter()
+            .map(format_value)
+            .collect()
+    } else {
+        values
+            .split(',')
+            .map(|value| parse_param_value(value).map(|_| value.trim().to_string()))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    Ok(SweepAxis {
+        name: name.to_string(),
+        values,
+    })
+}
+
+// `steps` values from `start` to `end` inclusive, evenly spaced on a linear or log scale.
+fn range_values(start: f64, end: f64, scale: &str, steps: usize) -> Result<Vec<f64>, String> {
+    let last = (steps - 1) as f64;
+    match scale {
+        "lin" => Ok((0..steps)
+            .map(|step| start + (end - start) * step as f64 / last)
+            .collect()),
+        "log" => {
+            if start <= 0.0 || end <= 0.0 {
+                return Err(format!(
+                    "a log range needs positive ends, got {}..{}",
+                    start, end
+                ));
+            }
+            let ratio = end / start;
+            Ok((0..steps)
+                .map(|step| start * ratio.powf(step as f64 / last))
+                .collect())
+        }
+        _ => Err(format!("'{}' is not lin or log", scale)),
+    }
+}
+
+// Six significant digits without trailing zeros (338.844, 1200, 0.5), so computed grid
+// values make readable file names.
+fn format_value(value: f64) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let decimals = (5 - value.abs().log10().floor() as i32).max(0) as usize;
+    let text = format!("{:.*}", decimals, value);
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        text
+    }
+}
+
+// Every combination of the axes' values, the first axis varying slowest.
+fn grid(axes: &[SweepAxis]) -> Vec<Vec<(String, String)>> {
+    let mut points = vec![Vec::new()];
+    for axis in axes {
+        points = points
+            .into_iter()
+            .flat_map(|point: Vec<(String, String)>| {
+                axis.values.iter().map(move |value| {
+                    let mut point = point.clone();
+                    point.push((axis.name.clone(), value.clone()));
+                    point
+                })
+            })
+            .collect();
+    }
+    points
+}
+
+// The grid points to render, each with the fixed --param values given to the main
+// command first, once for each --preset. A parameter can only be swept once, and not also
+// be fixed; swept values replace a preset's.
+pub(crate) fn sweep_points(
+    axes: &[SweepAxis],
+    options: &RenderOptions,
+) -> Result<Vec<ParamSet>, String> {
+    for (index, axis) in axes.iter().enumerate() {
+        if axes[..index].iter().any(|other| other.name == axis.name) {
+            return Err(format!("--param {} is swept more than once", axis.name));
+        }
+        if options.params.iter().any(|(name, _)| *name == axis.name) {
+            return Err(format!(
+                "--param {} is given both as a fixed value and as a sweep",
+                axis.name
+            ));
+        }
+    }
+
+    let grid = grid(axes);
+    Ok(options
+        .preset_choices()
+        .into_iter()
+        .flat_map(|preset| {
+            grid.iter().map(move |point| ParamSet {
+                preset: preset.clone(),
+                params: options.params.iter().chain(point).cloned().collect(),
+            })
+        })
+        .collect())
+}
+
+// Renders every source file with every program at every grid point, into a new
+// `sweep_<timestamp>` folder under the result folder, and writes `index.csv` there.
+pub(crate) fn run_sweep(
+    audio_files: &[InputGroup],
+    program_paths: &[String],
+    options: &RenderOptions,
+    axes: &[SweepAxis],
+    points: &[ParamSet],
+) -> Result<(), String> {
+    let folder = RESULT_FOLDER.join(format!(
+        "sweep_{}",
+        Local::now().format("%Y_%m_%d_%H_%M_%S")
+    ));
+    fs::create_dir_all(&folder)
+        .map_err(|e| format!("Error creating {}: {}", folder.display(), e))?;
+
+    let mut dimensions: Vec<String> = axes
+        .iter()
+        .map(|axis| format!("{} × {}", axis.name, axis.values.len()))
+        .collect();
+    if !options.presets.is_empty() {
+        dimensions.push(format!("presets × {}", options.presets.len()));
+    }
+    println!(
+        "Sweep: {} ({} points) into {}",
+        dimensions.join(", "),
+        points.len(),
+        folder.display()
+    );
+
+    let mut completed = render_files(audio_files, program_paths, options, points, &folder);
+    // Renders finish in any order; list them by source, program, rate and grid point.
+    completed.sort_by_key(|render| {
+        (
+            render.source.clone(),
+            render.language.clone(),
+            render.sample_rate,
+            points.iter().position(|point| *point == render.param_set),
+        )
+    });
+
+    let index_path = folder.join("index.csv");
+    write_index(&index_path, points, &completed)
+        .map_err(|e| format!("Error writing {}: {}", index_path.display(), e))?;
+    println!("Index: {}", index_path.display());
+    Ok(())
+}
+
+// One row per output file: its name, source, language, render rate, preset (with --preset)
+// and parameter values.
+fn write_index(path: &Path, points: &[ParamSet], completed: &[CompletedRender]) -> io::Result<()> {
+    let names: Vec<&str> = points
+        .first()
+        .map(|point| point.params.iter().map(|(name, _)| name.as_str()).collect())
+        .unwrap_or_default();
+    let with_presets = points.iter().any(|point| point.preset.is_some());
+
+    let mut csv = String::new();
+    let header: Vec<&str> = ["file", "source", "language", "sample_rate"]
+        .into_iter()
+        .chain(with_presets.then_some("preset"))
+        .chain(names.iter().copied())
+        .collect();
+    csv.push_str(&header.join(","));
+    csv.push('\n');
+
+    for render in completed {
+        for output in &render.outputs {
+            let file = output
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut row = vec![
+                csv_field(&file),
+                csv_field(&render.source),
+                csv_field(&render.language),
+                render
+                    .sample_rate
+                    .map(|rate| rate.to_string())
+                    .unwrap_or_default(),
+            ];
+            if with_presets {
+                let preset = render.param_set.preset.as_ref();
+                row.push(csv_field(preset.map_or("", |preset| &preset.name)));
+            }
+            row.extend(
+                render
+                    .param_set
+                    .params
+                    .iter()
+                    .map(|(_, value)| csv_field(value)),
+            );
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+    }
+
+    fs::write(path, csv)
+}
+
+// Quotes a field that contains a comma, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(spec: &str) -> Vec<String> {
+        parse_sweep_param(spec).unwrap().values
+    }
+
+    #[test]
+    fn parses_ranges_and_lists() {
+        let axis = parse_sweep_param("mix=0..1:lin:5").unwrap();
+        assert_eq!(axis.name, "mix");
+        assert_eq!(axis.values, ["0", "0.25", "0.5", "0.75", "1"]);
+        assert_eq!(
+            values("cutoff=20..20000:log:4"),
+            ["20", "200", "2000", "20000"]
+        );
+        assert_eq!(values("gain=6..-6:lin:3"), ["6", "0", "-6"]);
+        // List values are kept as written.
+        assert_eq!(values("q=0.7, 1.0,2"), ["0.7", "1.0", "2"]);
+    }
+
+    #[test]
+    fn rejects_bad_sweeps() {
+        for spec in [
+            "mix",
+            "m ix=0..1:lin:5",
+            "mix=0..1",
+            "mix=0..1:lin",
+            "mix=0..1:lin:5:6",
+            "mix=0..1:cubic:5",
+            "mix=0..1:lin:1",
+            "mix=0..1:lin:many",
+            "mix=a..1:lin:5",
+            "cutoff=0..20000:log:4",
+            "cutoff=-20..20000:log:4",
+            "q=0.7,,2",
+            "q=0.7,warm",
+        ] {
+            assert!(parse_sweep_param(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+
+    #[test]
+    fn formats_values_to_six_significant_digits() {
+        assert_eq!(format_value(0.0), "0");
+        assert_eq!(format_value(1200.0), "1200");
+        assert_eq!(format_value(0.5), "0.5");
+        assert_eq!(format_value(338.844_156), "338.844");
+        assert_eq!(format_value(-0.25), "-0.25");
+        assert_eq!(format_value(0.000_000_123_456_7), "0.000000123457");
+        assert_eq!(format_value(123_456_789.0), "123456789");
+        assert_eq!(format_value(200.000_000_000_03), "200");
+    }
+
+    #[test]
+    fn grid_varies_the_first_axis_slowest() {
+        let axis = |name: &str, values: &[&str]| SweepAxis {
+            name: name.to_string(),
+            values: values.iter().map(|value| value.to_string()).collect(),
+        };
+        let points = grid(&[axis("a", &["1", "2"]), axis("b", &["x", "y", "z"])]);
+        let points: Vec<String> = points
+            .iter()
+            .map(|point| {
+                point
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        assert_eq!(
+            points,
+            [
+                "a=1 b=x", "a=1 b=y", "a=1 b=z", "a=2 b=x", "a=2 b=y", "a=2 b=z"
+            ]
+        );
+        assert_eq!(grid(&[]), vec![Vec::<(String, String)>::new()]);
+    }
+}
@@ -1,25 +1,87 @@
 use crate::constants::constants::*;
+use crate::file_processing::audio_processing::group_multi_mono::InputGroup;
+use crate::file_processing::code_processing::hash_program_sources::hash_program_sources;
+use crate::signal_processing::render_options::{ParamSet, RenderOptions, param_tag};
 use chrono::Local;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
 
-pub(crate) fn process_multiple_audio_files(audio_files: &[String], program_paths: &[String], preserve_meta: bool) {
+// A render that finished: the files it wrote and what it was rendered with.
+pub(crate) struct CompletedRender {
+    pub(crate) outputs: Vec<PathBuf>,
+    pub(crate) source: String,
+    pub(crate) language: String,
+    pub(crate) sample_rate: Option<u32>,
+    pub(crate) param_set: ParamSet,
+}
+
+pub(crate) fn process_multiple_audio_files(
+    audio_files: &[InputGroup],
+    program_paths: &[String],
+    options: &RenderOptions,
+) {
+    render_files(
+        audio_files,
+        program_paths,
+        options,
+        &options.param_sets(),
+        &RESULT_FOLDER,
+    );
+}
+
+// Renders every combination of input group, program, rate and parameter set in parallel,
+// writing into `output_folder`. Returns the renders that succeeded.
+pub(crate) fn render_files(
+    audio_files: &[InputGroup],
+    program_paths: &[String],
+    options: &RenderOptions,
+    param_sets: &[ParamSet],
+    output_folder: &Path,
+) -> Vec<CompletedRender> {
     let runtime_binary = std::path::PathBuf::from("../audio/.playdsp_runtime/target/release")
         .join(format!("playdsp_runtime{}", std::env::consts::EXE_SUFFIX));
 
     if !runtime_binary.exists() {
         eprintln!("Runtime binary not found. This shouldn't happen after recompilation.");
-        return;
+        return Vec::new();
+    }
+
+    // --provenance records a hash of the code folder each program was compiled from.
+    let mut source_hashes = HashMap::new();
+    if options.provenance.is_some() {
+        for program_path in program_paths {
+            let folder = Path::new(program_path.as_str())
+                .parent()
+                .unwrap_or(Path::new("."));
+            match hash_program_sources(folder) {
+                Ok(hash) => {
+                    source_hashes.insert(program_path.as_str(), hash);
+                }
+                Err(e) => {
+                    eprintln!("Error hashing {}: {}", folder.display(), e);
+                    return Vec::new();
+                }
+            }
+        }
     }
 
-    let pairs: Vec<(&String, &String)> = audio_files
+    let render_rates = options.render_rates();
+    let renders: Vec<_> = audio_files
         .iter()
         .flat_map(|audio| program_paths.iter().map(move |prog| (audio, prog)))
+        .flat_map(|(audio, prog)| render_rates.iter().map(move |&rate| (audio, prog, rate)))
+        .flat_map(|(audio, prog, rate)| {
+            param_sets
+                .iter()
+                .map(move |param_set| (audio, prog, rate, param_set))
+        })
         .collect();
 
-    let pb = ProgressBar::new(pairs.len() as u64);
+    let pb = ProgressBar::new(renders.len() as u64);
     pb.set_style(
         ProgressStyle::default_bar()
             .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")
@@ -28,42 +90,94 @@ pub(crate) fn process_multiple_audio_files(audio_files: &[String], program_paths
     );
 
     let processing_start = std::time::Instant::now();
+    let completed = Mutex::new(Vec::new());
 
-    pairs.par_iter().for_each(|(audio_file, program_path)| {
+    renders.par_iter().for_each(|render| {
+        let &(group, program_path, rate, param_set) = render;
         let current_time = Local::now().format("%Y_%m_%d_%H_%M_%S_%3f").to_string();
-        let audio_stem = Path::new(audio_file.as_str())
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("unknown");
+        let audio_file = group.files.join(" + ");
 
         let program_suffix = Path::new(program_path.as_str())
             .extension()
             .and_then(|ext| ext.to_str())
             .unwrap_or("");
 
+        // Renders at a chosen rate are tagged with it, so a rate matrix gives one file per rate.
+        let rate_tag = rate.map(|rate| format!("_{}hz", rate)).unwrap_or_default();
+        // A preset's name follows the language; --param values follow the rate.
+        let preset_tag = param_set.preset_tag();
+        let param_tag = param_tag(&param_set.params);
+
+        // A split multi-mono render gets one output per source file, ending in that file's
+        // suffix (`take.L.wav` -> `take_processed_..._rs.L.wav`).
+        let output_suffixes = if options.split_multi_mono {
+            group.suffixes.clone()
+        } else {
+            vec![String::new()]
+        };
+
         if Path::new(program_path.as_str()).exists() {
-            let output_file = RESULT_FOLDER.join(format!(
-                "{}_processed_{}_{}.wav",
-                audio_stem, current_time, program_suffix
-            ));
+            let output_files: Vec<_> = output_suffixes
+                .iter()
+                .map(|suffix| {
+                    output_folder.join(format!(
+                        "{}_processed_{}_{}{}{}{}{}.{}",
+                        group.name,
+                        current_time,
+                        program_suffix,
+                        preset_tag,
+                        rate_tag,
+                        param_tag,
+                        suffix,
+                        options.output_extension()
+                    ))
+                })
+                .collect();
 
+            let source_hash = source_hashes
+                .get(program_path.as_str())
+                .map_or("", String::as_str);
             let mut cmd = Command::new(&runtime_binary);
-            cmd.arg(audio_file.as_str())
-                .arg(&output_file)
-                .arg(program_suffix);
-            if preserve_meta {
-                cmd.arg("--meta");
+            cmd.arg(&group.files[0])
+                .arg(&output_files[0])
+                .arg(program_suffix)
+                .args(options.runtime_args(rate, &param_set.values(), source_hash))
+                .args(options.input_args(group));
+            for file in &output_files[1..] {
+                cmd.arg("--split-output").arg(file);
             }
+            let outputs = output_files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
 
-            match cmd.status() {
-                Ok(exit_status) if exit_status.success() => {
-                    pb.println(format!("  → {}", output_file.display()));
-                }
-                Ok(exit_status) => {
-                    pb.println(format!(
-                        "  ✗ {}: runtime exited with {}",
-                        audio_file, exit_status
-                    ));
+            match cmd.output() {
+                Ok(output) => {
+                    let mut report = if !output.status.success() {
+                        format!("  ✗ {}: runtime exited with {}", audio_file, output.status)
+                    } else if group.files.iter().any(|file| is_lossy_source(file)) {
+                        format!("  → {} (lossy source, not bit-exact)", outputs)
+                    } else {
+                        format!("  → {}", outputs)
+                    };
+                    // Runtime messages (clipping reports, errors) are shown under the file
+                    // they belong to, and printed in one go so parallel renders don't interleave.
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    for line in stdout.lines().chain(stderr.lines()) {
+                        report.push_str(&format!("\n      {}", line));
+                    }
+                    pb.suspend(|| println!("{}", report));
+                    if output.status.success() {
+                        completed.lock().unwrap().push(CompletedRender {
+                            outputs: output_files,
+                            source: audio_file,
+                            language: program_suffix.to_string(),
+                            sample_rate: rate,
+                            param_set: param_set.clone(),
+                        });
+                    }
                 }
                 Err(e) => {
                     pb.println(format!("  ✗ runtime error: {}", e));
@@ -81,4 +195,17 @@ pub(crate) fn process_multiple_audio_files(audio_files: &[String], program_paths
         "All files processed in {:.1}s",
         processing_start.elapsed().as_secs_f64()
     );
+    completed.into_inner().unwrap()
+}
+
+fn is_lossy_source(audio_file: &str) -> bool {
+    Path::new(audio_file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            LOSSY_AUDIO_EXTENSIONS
+                .iter()
+                .any(|lossy| ext.eq_ignore_ascii_case(lossy))
+        })
+        .unwrap_or(false)
 }
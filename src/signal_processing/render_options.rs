@@ -0,0 +1,552 @@
+use crate::constants::constants::*;
+use crate::file_processing::audio_processing::find_midi_file::find_midi_file;
+use crate::file_processing::audio_processing::group_multi_mono::{GroupingRule, InputGroup};
+use crate::file_processing::code_processing::load_presets::{Preset, load_presets};
+use clap::ArgMatches;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Options forwarded to every playdsp_runtime invocation.
+pub(crate) struct RenderOptions {
+    // Metadata carried over with --meta: `all` or a list of META_CHUNKS.
+    pub(crate) meta: Option<String>,
+    // Where --provenance records how each file was rendered: `ixml`, or `bext` for the
+    // iXML chunk plus a summary in the bext description.
+    pub(crate) provenance: Option<String>,
+    // Values for parameters the DSP code declares, as (name, value) in the order given.
+    pub(crate) params: Vec<(String, String)>,
+    // Presets selected with --preset; every file is rendered once with each.
+    pub(crate) presets: Vec<Preset>,
+    // Breakpoint file moving parameters during the render, and whether the runtime passes
+    // the values on at every `sample` or once per `block`.
+    pub(crate) automation: Option<String>,
+    pub(crate) automation_rate: Option<String>,
+    // MIDI file given with --midi, delivered with every source instead of the MIDI file
+    // next to it.
+    pub(crate) midi: Option<String>,
+    pub(crate) buffer_size: u32,
+    pub(crate) block_sizes: Option<String>,
+    pub(crate) seed: u64,
+    pub(crate) output_container: String,
+    pub(crate) output_format: String,
+    pub(crate) dither: String,
+    pub(crate) noise_shaping: String,
+    pub(crate) dither_seed: u64,
+    // Rates every file is rendered at; empty renders at the source's own rate.
+    pub(crate) sample_rates: Vec<u32>,
+    pub(crate) restore_rate: bool,
+    pub(crate) multi_mono: Vec<GroupingRule>,
+    pub(crate) split_multi_mono: bool,
+}
+
+impl RenderOptions {
+    pub(crate) fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        let seed = matches
+            .get_one::<u64>(SEED_NAME)
+            .copied()
+            .unwrap_or_else(generate_seed);
+
+        let output_container = matches
+            .get_one::<String>(OUTPUT_CONTAINER_NAME)
+            .cloned()
+            .unwrap_or_else(|| "wav".to_string());
+        let output_format = match matches.get_one::<String>(OUTPUT_FORMAT_NAME) {
+            Some(format) => format.clone(),
+            None if output_container == "flac" => DEFAULT_FLAC_OUTPUT_FORMAT.to_string(),
+            None => DEFAULT_OUTPUT_FORMAT.to_string(),
+        };
+        if output_container == "flac" && !FLAC_OUTPUT_FORMATS.contains(&output_format.as_str()) {
+            return Err(format!(
+                "FLAC output is 16 or 24 bit; use --output-format {} with --output-container flac",
+                FLAC_OUTPUT_FORMATS.join(" or ")
+            ));
+        }
+
+        let sample_rates = match (
+            matches.get_one::<u32>(SAMPLE_RATE_NAME),
+            matches.get_one::<Vec<u32>>(RATE_MATRIX_NAME),
+        ) {
+            (Some(&rate), _) => vec![rate],
+            (None, Some(rates)) => rates.clone(),
+            (None, None) => Vec::new(),
+        };
+        let params = collect_params(matches)?;
+        let presets = match matches.get_one::<String>(PRESET_NAME) {
+            Some(selection) => load_presets(selection)?,
+            None => Vec::new(),
+        };
+        let automation = matches.get_one::<String>(AUTOMATION_NAME).cloned();
+        if let Some(path) = &automation {
+            if !std::path::Path::new(path).is_file() {
+                return Err(format!("Automation file '{}' not found", path));
+            }
+        }
+        let midi = matches.get_one::<String>(MIDI_NAME).cloned();
+        if let Some(path) = &midi {
+            if !std::path::Path::new(path).is_file() {
+                return Err(format!("MIDI file '{}' not found", path));
+            }
+        }
+        let restore_rate = matches.get_flag(RESTORE_RATE_NAME);
+        if restore_rate && sample_rates.is_empty() {
+            return Err("--restore-rate needs --sample-rate or --rate-matrix".to_string());
+        }
+
+        Ok(RenderOptions {
+            meta: matches.get_one::<String>(META_NAME).cloned(),
+            provenance: matches.get_one::<String>(PROVENANCE_NAME).cloned(),
+            params,
+            presets,
+            automation,
+            automation_rate: matches.get_one::<String>(AUTOMATION_RATE_NAME).cloned(),
+            midi,
+            buffer_size: matches
+                .get_one::<u32>(BUFFER_SIZE_NAME)
+                .copied()
+                .unwrap_or(DEFAULT_BUFFER_SIZE),
+            block_sizes: matches.get_one::<String>(BLOCK_SIZES_NAME).cloned(),
+            seed,
+            output_container,
+            output_format,
+            dither: matches
+                .get_one::<String>(DITHER_NAME)
+                .cloned()
+                .unwrap_or_else(|| "tpdf".to_string()),
+            noise_shaping: matches
+                .get_one::<String>(NOISE_SHAPING_NAME)
+                .cloned()
+                .unwrap_or_else(|| "none".to_string()),
+            dither_seed: matches
+                .get_one::<u64>(DITHER_SEED_NAME)
+                .copied()
+                .unwrap_or(0),
+            sample_rates,
+            restore_rate,
+            multi_mono: matches
+                .get_many::<GroupingRule>(MULTI_MONO_NAME)
+                .map(|rules| rules.cloned().collect())
+                .unwrap_or_default(),
+            split_multi_mono: matches.get_flag(SPLIT_MULTI_MONO_NAME),
+        })
+    }
+
+    // One entry per render of each file: the preset to render with, or None without --preset.
+    pub(crate) fn preset_choices(&self) -> Vec<Option<Preset>> {
+        if self.presets.is_empty() {
+            vec![None]
+        } else {
+            self.presets.iter().cloned().map(Some).collect()
+        }
+    }
+
+    // The parameter values of each render of a file: one set per preset choice, with the
+    // --param values on top.
+    pub(crate) fn param_sets(&self) -> Vec<ParamSet> {
+        self.preset_choices()
+            .into_iter()
+            .map(|preset| ParamSet {
+                preset,
+                params: self.params.clone(),
+            })
+            .collect()
+    }
+
+    // The MIDI file delivered while `group` renders: --midi, or the one next to its first file.
+    pub(crate) fn midi_file(&self, group: &InputGroup) -> Option<String> {
+        self.midi
+            .clone()
+            .or_else(|| find_midi_file(&group.files[0], &group.name))
+    }
+
+    // One entry per render of each file: the rate to render at, or None for the source rate.
+    pub(crate) fn render_rates(&self) -> Vec<Option<u32>> {
+        if self.sample_rates.is_empty() {
+            vec![None]
+        } else {
+            self.sample_rates.iter().copied().map(Some).collect()
+        }
+    }
+
+    pub(crate) fn uses_random_block_sizes(&self) -> bool {
+        self.block_sizes
+            .as_deref()
+            .is_some_and(|spec| spec.starts_with("random:"))
+    }
+
+    // File extension of the rendered files.
+    pub(crate) fn output_extension(&self) -> &str {
+        &self.output_container
+    }
+
+    // Runtime arguments that read `group` after its first file: the other multi-mono files,
+    // the speaker layout they merge into and the MIDI file delivered with them.
+    pub(crate) fn input_args(&self, group: &InputGroup) -> Vec<String> {
+        let mut args = Vec::new();
+        for file in &group.files[1..] {
+            args.push("--group-input".to_string());
+            args.push(file.clone());
+        }
+        // Mono files carry no speaker layout, so a merged group gets it from its channel names.
+        if let Some(mask) = group.channel_mask {
+            args.push("--channel-mask".to_string());
+            args.push(mask.to_string());
+        }
+        if let Some(midi) = self.midi_file(group) {
+            args.push("--midi".to_string());
+            args.push(midi);
+        }
+        args
+    }
+
+    // `params` are the --param values of this render (the sweep grid point in a sweep), and
+    // `source_hash` is the hash of the DSP code being rendered, recorded with --provenance.
+    pub(crate) fn runtime_args(
+        &self,
+        sample_rate: Option<u32>,
+        params: &[(String, String)],
+        source_hash: &str,
+    ) -> Vec<String> {
+        let mut args = vec!["--buffer-size".to_string(), self.buffer_size.to_string()];
+        if let Some(spec) = &self.block_sizes {
+            args.push("--block-sizes".to_string());
+            args.push(spec.clone());
+            args.push("--seed".to_string());
+            args.push(self.seed.to_string());
+        }
+        if let Some(chunks) = &self.meta {
+            args.push("--meta".to_string());
+            args.push(chunks.clone());
+        }
+        args.extend(param_args(params));
+        if let Some(path) = &self.automation {
+            args.push("--automation".to_string());
+            args.push(path.clone());
+            if let Some(rate) = &self.automation_rate {
+                args.push("--automation-rate".to_string());
+                args.push(rate.clone());
+            }
+        }
+        if let Some(target) = &self.provenance {
+            args.extend([
+                "--provenance".to_string(),
+                target.clone(),
+                "--playdsp-version".to_string(),
+                env!("CARGO_PKG_VERSION").to_string(),
+                "--source-hash".to_string(),
+                source_hash.to_string(),
+            ]);
+        }
+        args.extend([
+            "--output-container".to_string(),
+            self.output_container.clone(),
+            "--output-format".to_string(),
+            self.output_format.clone(),
+            "--dither".to_string(),
+            self.dither.clone(),
+            "--noise-shaping".to_string(),
+            self.noise_shaping.clone(),
+            "--dither-seed".to_string(),
+            self.dither_seed.to_string(),
+        ]);
+        if let Some(rate) = sample_rate {
+            args.push("--sample-rate".to_string());
+            args.push(rate.to_string());
+            if self.restore_rate {
+                args.push("--restore-rate".to_string());
+            }
+        }
+        args
+    }
+}
+
+// The parameter values of one render: a preset, if any, with `params` (--param values, and
+// the grid point in a sweep) on top.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ParamSet {
+    pub(crate) preset: Option<Preset>,
+    pub(crate) params: Vec<(String, String)>,
+}
+
+impl ParamSet {
+    // Every value passed to the runtime: the preset's values for parameters `params` does
+    // not give, then `params`.
+    pub(crate) fn values(&self) -> Vec<(String, String)> {
+        let preset_params = self.preset.iter().flat_map(|preset| &preset.params);
+        let mut values: Vec<(String, String)> = preset_params
+            .filter(|(name, _)| !self.params.iter().any(|(given, _)| given == name))
+            .cloned()
+            .collect();
+        values.extend(self.params.iter().cloned());
+        values
+    }
+
+    // Appended to output file names after the language, e.g. `_warm_tape`.
+    pub(crate) fn preset_tag(&self) -> String {
+        self.preset
+            .as_ref()
+            .map(|preset| format!("_{}", preset.name))
+            .unwrap_or_default()
+    }
+}
+
+// Validates a --block-sizes spec: `random:<min>-<max>` or `list:<n>,<n>,...`.
+pub(crate) fn parse_block_sizes(spec: &str) -> Result<String, String> {
+    if let Some(range) = spec.strip_prefix("random:") {
+        let (min, max) = range
+            .split_once('-')
+            .ok_or_else(|| format!("expected random:<min>-<max>, got '{}'", spec))?;
+        let (min, max) = (parse_block_size(min)?, parse_block_size(max)?);
+        if min > max {
+            return Err(format!(
+                "minimum block size {} is larger than maximum {}",
+                min, max
+            ));
+        }
+    } else if let Some(list) = spec.strip_prefix("list:") {
+        for value in list.split(',') {
+            parse_block_size(value)?;
+        }
+    } else {
+        return Err(format!(
+            "expected random:<min>-<max> or list:<n>,<n>,..., got '{}'",
+            spec
+        ));
+    }
+
+    Ok(spec.to_string())
+}
+
+// The --param values of `matches`; a parameter may only be given once.
+pub(crate) fn collect_params(matches: &ArgMatches) -> Result<Vec<(String, String)>, String> {
+    let mut params: Vec<(String, String)> = Vec::new();
+    for (name, value) in matches
+        .get_many::<(String, String)>(PARAM_NAME)
+        .into_iter()
+        .flatten()
+    {
+        if params.iter().any(|(given, _)| given == name) {
+            return Err(format!("--param {} is given more than once", name));
+        }
+        params.push((name.clone(), value.clone()));
+    }
+    Ok(params)
+}
+
+// Appended to output file names so renders with different values sit side by side,
+// e.g. `_cutoff=1200_q=0.7`.
+pub(crate) fn param_tag(params: &[(String, String)]) -> String {
+    params
+        .iter()
+        .map(|(name, value)| format!("_{}={}", name, value))
+        .collect()
+}
+
+// Runtime arguments passing `params` on.
+pub(crate) fn param_args(params: &[(String, String)]) -> Vec<String> {
+    params
+        .iter()
+        .flat_map(|(name, value)| ["--param".to_string(), format!("{}={}", name, value)])
+        .collect()
+}
+
+// Parses a --param value such as `cutoff=1200`. Names are letters, digits and underscores
+// and values are finite numbers, so both can go into output file names as given.
+pub(crate) fn parse_param(spec: &str) -> Result<(String, String), String> {
+    let (name, value) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected <name>=<value>, got '{}'", spec))?;
+    check_param_name(name)?;
+    parse_param_value(value)?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+pub(crate) fn check_param_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "'{}' is not a parameter name (letters, digits and _)",
+            name
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_param_value(value: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| format!("'{}' is not a number", value))
+}
+
+// Validates a --meta list: `all`, or metadata names from META_CHUNKS separated by commas.
+pub(crate) fn parse_meta_chunks(spec: &str) -> Result<String, String> {
+    if spec == "all" {
+        return Ok(spec.to_string());
+    }
+    for chunk in spec.split(',') {
+        if !META_CHUNKS.contains(&chunk) {
+            return Err(format!(
+                "'{}' is not all or one of {}",
+                chunk,
+                META_CHUNKS.join(", ")
+            ));
+        }
+    }
+    Ok(spec.to_string())
+}
+
+// Parses a --rate-matrix list such as `44100,48000,96000`.
+pub(crate) fn parse_rate_matrix(spec: &str) -> Result<Vec<u32>, String> {
+    let mut rates = Vec::new();
+    for value in spec.split(',') {
+        let rate = value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|&rate| rate > 0)
+            .ok_or_else(|| format!("'{}' is not a sample rate in Hz", value))?;
+        if !rates.contains(&rate) {
+            rates.push(rate);
+        }
+    }
+    Ok(rates)
+}
+
+pub(crate) fn parse_block_size(value: &str) -> Result<usize, String> {
+    value
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("'{}' is not a positive block size", value))
+}
+
+// Parses the --tolerance of verify-blocksize: the largest allowed per-sample difference.
+pub(crate) fn parse_tolerance(value: &str) -> Result<f64, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|tolerance| tolerance.is_finite() && *tolerance >= 0.0)
+        .ok_or_else(|| format!("'{}' is not a non-negative tolerance", value))
+}
+
+pub(crate) fn generate_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_block_size_specs() {
+        assert_eq!(
+            parse_block_sizes("random:32-512"),
+            Ok("random:32-512".to_string())
+        );
+        assert_eq!(
+            parse_block_sizes("random:64-64"),
+            Ok("random:64-64".to_string())
+        );
+        assert_eq!(
+            parse_block_sizes("list:1,17, 512"),
+            Ok("list:1,17, 512".to_string())
+        );
+        assert_eq!(parse_block_sizes("list:256"), Ok("list:256".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_block_size_specs() {
+        for spec in [
+            "",
+            "32-512",
+            "random:",
+            "random:512",
+            "random:512-32",
+            "random:0-64",
+            "random:a-64",
+            "list:",
+            "list:64,",
+            "list:64,0",
+            "list:-1",
+            "fixed:64",
+        ] {
+            assert!(parse_block_sizes(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+
+    #[test]
+    fn parses_params() {
+        let param = |name: &str, value: &str| Ok((name.to_string(), value.to_string()));
+        assert_eq!(parse_param("cutoff=1200"), param("cutoff", "1200"));
+        assert_eq!(parse_param("drive_2=-0.5"), param("drive_2", "-0.5"));
+        assert_eq!(parse_param("mix=1e-3"), param("mix", "1e-3"));
+        // Only the first = separates the name from the value.
+        assert!(parse_param("q=0.7=1").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_params() {
+        for spec in [
+            "cutoff",
+            "=1200",
+            "cut off=1200",
+            "cutoff-hz=1200",
+            "cutoff=",
+            "cutoff=warm",
+            "cutoff=inf",
+            "cutoff=NaN",
+        ] {
+            assert!(parse_param(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+
+    #[test]
+    fn parses_tolerances() {
+        assert_eq!(parse_tolerance("1e-9"), Ok(1e-9));
+        assert_eq!(parse_tolerance("0"), Ok(0.0));
+        assert_eq!(parse_tolerance(" 0.5"), Ok(0.5));
+        for value in ["", "-1e-9", "NaN", "inf", "loose"] {
+            assert!(parse_tolerance(value).is_err(), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn params_override_preset_values() {
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+        let set = ParamSet {
+            preset: Some(Preset {
+                name: "warm".to_string(),
+                params: vec![pair("drive", "0.8"), pair("cutoff", "4500")],
+            }),
+            params: vec![pair("cutoff", "1200"), pair("q", "0.7")],
+        };
+        assert_eq!(
+            set.values(),
+            [
+                pair("drive", "0.8"),
+                pair("cutoff", "1200"),
+                pair("q", "0.7")
+            ]
+        );
+        assert_eq!(set.preset_tag(), "_warm");
+    }
+
+    #[test]
+    fn tags_and_forwards_params() {
+        let params = vec![
+            ("cutoff".to_string(), "1200".to_string()),
+            ("q".to_string(), "0.7".to_string()),
+        ];
+        assert_eq!(param_tag(&params), "_cutoff=1200_q=0.7");
+        assert_eq!(param_tag(&[]), "");
+        assert_eq!(
+            param_args(&params),
+            ["--param", "cutoff=1200", "--param", "q=0.7"]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::file_processing::audio_processing::group_multi_mono::InputGroup;
+use crate::signal_processing::render_options::{RenderOptions, parse_block_size};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use std::path::Path;
+use std::process::Command;
+
+// Renders every (input group, program, rate, parameter set) combination at each block size
+// in `sizes` and compares the outputs sample by sample. Every render is given the same
+// runtime arguments as a normal render with `options`, so presets, automation, multi-mono
+// groups and MIDI files are checked too. Returns true when every combination renders
+// identically within `tolerance`.
+pub(crate) fn verify_block_sizes(
+    audio_files: &[InputGroup],
+    program_paths: &[String],
+    options: &RenderOptions,
+    sizes: &str,
+    tolerance: f64,
+) -> bool {
+    let runtime_binary = std::path::PathBuf::from("../audio/.playdsp_runtime/target/release")
+        .join(format!("playdsp_runtime{}", std::env::consts::EXE_SUFFIX));
+
+    if !runtime_binary.exists() {
+        eprintln!("Runtime binary not found. This shouldn't happen after recompilation.");
+        return false;
+    }
+
+    let render_rates = options.render_rates();
+    let param_sets = options.param_sets();
+    let checks: Vec<_> = audio_files
+        .iter()
+        .flat_map(|audio| program_paths.iter().map(move |prog| (audio, prog)))
+        .flat_map(|(audio, prog)| render_rates.iter().map(move |&rate| (audio, prog, rate)))
+        .flat_map(|(audio, prog, rate)| {
+            param_sets
+                .iter()
+                .map(move |param_set| (audio, prog, rate, param_set))
+        })
+        .collect();
+
+    let pb = ProgressBar::new(checks.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("=>-"),
+    );
+
+    let verify_start = std::time::Instant::now();
+
+    let results: Vec<bool> = checks
+        .par_iter()
+        .map(|&(group, program_path, rate, param_set)| {
+            let program_suffix = Path::new(program_path.as_str())
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("");
+            // Tagged like the output file name of the same render.
+            let label = format!(
+                "{} [{}{}{}]",
+                group.files.join(" + "),
+                program_suffix,
+                param_set.preset_tag(),
+                rate.map(|rate| format!("_{}hz", rate)).unwrap_or_default()
+            );
+
+            let passed = if Path::new(program_path.as_str()).exists() {
+                let output = Command::new(&runtime_binary)
+                    .arg(&group.files[0])
+                    .arg("-")
+                    .arg(program_suffix)
+                    .args(options.runtime_args(rate, &param_set.values(), ""))
+                    .args(options.input_args(group))
+                    .arg("--verify-block-sizes")
+                    .arg(sizes)
+                    .arg("--tolerance")
+                    .arg(tolerance.to_string())
+                    .arg("--seed")
+                    .arg(options.seed.to_string())
+                    .output();
+
+                match output {
+                    Ok(output) => {
+                        // Print the whole report at once so parallel reports don't interleave, and
+                        // through suspend() so it is still shown when stdout is not a terminal.
+                        let mark = if output.status.success() {
+                            "✓"
+                        } else {
+                            "✗"
+                        };
+                        let mut report = format!("  {} {}", mark, label);
+                        let stdout = String::from_utf8_lossy(&output.stdout);
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+                        for line in stdout.lines().chain(stderr.lines()) {
+                            report.push_str(&format!("\n      {}", line));
+                        }
+                        pb.suspend(|| println!("{}", report));
+                        output.status.success()
+                    }
+                    Err(e) => {
+                        pb.println(format!("  ✗ runtime error: {}", e));
+                        false
+                    }
+                }
+            } else {
+                pb.println(format!("  ✗ program file not found: {}", program_path));
+                false
+            };
+
+            pb.inc(1);
+            passed
+        })
+        .collect();
+
+    pb.finish_with_message("done");
+    println!(
+        "All files verified in {:.1}s",
+        verify_start.elapsed().as_secs_f64()
+    );
+
+    results.iter().all(|&passed| passed)
+}
+
+// Validates a --sizes list: comma-separated block sizes, where each entry is a positive
+// integer, `random`, or `random:<min>-<max>`.
+pub(crate) fn parse_verify_sizes(spec: &str) -> Result<String, String> {
+    let entries: Vec<&str> = spec.split(',').map(str::trim).collect();
+    if entries.len() < 2 {
+        return Err("at least two block sizes are needed to compare".to_string());
+    }
+
+    for entry in entries {
+        if entry == "random" {
+            continue;
+        }
+        if let Some(range) = entry.strip_prefix("random:") {
+            let (min, max) = range
+                .split_once('-')
+                .ok_or_else(|| format!("expected random:<min>-<max>, got '{}'", entry))?;
+            let min = parse_block_size(min)?;
+            let max = parse_block_size(max)?;
+            if min > max {
+                return Err(format!(
+                    "minimum block size {} is larger than maximum {}",
+                    min, max
+                ));
+            }
+        } else {
+            parse_block_size(entry)?;
+        }
+    }
+
+    Ok(spec.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_size_lists() {
+        for spec in [
+            "1,64",
+            "64, 512,random",
+            "random,random:16-256,1024",
+            "32,random:8-8",
+        ] {
+            assert_eq!(parse_verify_sizes(spec), Ok(spec.to_string()));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_size_lists() {
+        for spec in [
+            "",
+            "64",
+            "random",
+            "64,",
+            "0,64",
+            "64,big",
+            "64,random:",
+            "64,random:256-16",
+            "64,random:0-16",
+        ] {
+            assert!(parse_verify_sizes(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+}
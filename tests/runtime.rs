@@ -0,0 +1,314 @@
+// Runs the playdsp binary in a scratch project: the runtime's own unit tests, which live in
+// the templates and only compile inside a generated runtime, and renders checked end to end.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+// The audio folder of the scratch project, where playdsp runs. The project is kept in the
+// target directory so later runs reuse the runtime's built dependencies, and tests take
+// turns with it because every playdsp run rebuilds the runtime.
+fn project() -> MutexGuard<'static, PathBuf> {
+    static PROJECT: OnceLock<Mutex<PathBuf>> = OnceLock::new();
+    PROJECT
+        .get_or_init(|| {
+            let base = Path::new(env!("CARGO_TARGET_TMPDIR")).join("project");
+            let output = Command::new(env!("CARGO_BIN_EXE_playdsp"))
+                .arg("new")
+                .arg("--dir")
+                .arg(&base)
+                .output()
+                .expect("Failed to run playdsp new");
+            check(&output, "playdsp new");
+            Mutex::new(base.join("audio"))
+        })
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// playdsp run from the project's audio folder. The runtime is built without LTO, which
+// keeps the rebuild on every run short.
+fn playdsp(audio: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_playdsp"))
+        .args(args)
+        .current_dir(audio)
+        .env("CARGO_PROFILE_RELEASE_LTO", "false")
+        .env("CARGO_PROFILE_RELEASE_CODEGEN_UNITS", "16")
+        .output()
+        .expect("Failed to run playdsp");
+    check(&output, &format!("playdsp {}", args.join(" ")));
+    output
+}
+
+fn check(output: &Output, what: &str) {
+    assert!(
+        output.status.success(),
+        "{} failed:\n{}\n{}",
+        what,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn runtime_unit_tests_pass() {
+    let audio = project();
+    // playdsp test sets the runtime up for testing, but only runs the project's tests. The
+    // C++ starter tests hold whatever Rust processor the render tests left in the project.
+    let output = playdsp(&audio, &["test", "--cpp"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("user_code::"), "{}", stdout);
+    assert!(!stdout.contains("midi::tests"), "{}", stdout);
+
+    let output = Command::new("cargo")
+        .args(["test", "--", "--skip", "user_code::"])
+        .current_dir(audio.join(".playdsp_runtime"))
+        .output()
+        .expect("Failed to run cargo test");
+    check(&output, "cargo test in the runtime");
+}
+
+// Marker positions in the source, which is a second of 48 kHz audio with an impulse on each
+// marker. The second marker starts a region.
+const SOURCE_RATE: u32 = 48000;
+const MARKERS: [u32; 3] = [1200, 3000, 24000];
+const REGION_LENGTH: u32 = 600;
+
+// The Rust processor of the render tests: a delay that reports its latency, so every render
+// checks that trimming the latency leaves the markers on the audio they mark. The C++
+// starter code renders alongside it without latency.
+const DELAY_PROCESSOR: &str = r#"use crate::processor::{AudioBuffer, AudioProcessor};
+
+pub struct Delay {
+    lines: Vec<Vec<f64>>,
+}
+
+const DELAY: usize = 37;
+
+impl AudioProcessor for Delay {
+    fn prepare(&mut self, _sample_rate: f64, _max_block: usize, num_channels: usize) {
+        self.lines = vec![vec![0.0; DELAY]; num_channels];
+    }
+
+    fn process(&mut self, input: &AudioBuffer, output: &mut AudioBuffer) {
+        for ((input, output), line) in input.channels().zip(output.channels_mut()).zip(&mut self.lines) {
+            for (x, y) in input.iter().zip(output.iter_mut()) {
+                line.push(*x);
+                *y = line.remove(0);
+            }
+        }
+    }
+
+    fn latency_samples(&self) -> usize {
+        DELAY
+    }
+}
+
+crate::register_processor!(Delay { lines: Vec::new() });
+"#;
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+// 16-bit mono source with the markers in a cue chunk, a label on the first two and the
+// region as an ltxt entry in LIST/adtl.
+fn source_wav() -> Vec<u8> {
+    let mut samples = vec![0i16; SOURCE_RATE as usize];
+    for marker in MARKERS {
+        samples[marker as usize] = 16384;
+    }
+    let data: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+
+    let mut fmt = Vec::new();
+    for value in [1u16, 1] {
+        fmt.extend_from_slice(&value.to_le_bytes());
+    }
+    fmt.extend_from_slice(&SOURCE_RATE.to_le_bytes());
+    fmt.extend_from_slice(&(SOURCE_RATE * 2).to_le_bytes());
+    for value in [2u16, 16] {
+        fmt.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut cue = (MARKERS.len() as u32).to_le_bytes().to_vec();
+    for (index, marker) in MARKERS.iter().enumerate() {
+        let id = index as u32 + 1;
+        for field in [id, *marker] {
+            cue.extend_from_slice(&field.to_le_bytes());
+        }
+        cue.extend_from_slice(b"data");
+        for field in [0, 0, *marker] {
+            cue.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+
+    let mut adtl = b"adtl".to_vec();
+    adtl.extend(chunk(b"labl", b"\x01\0\0\0Hit\0"));
+    adtl.extend(chunk(b"labl", b"\x02\0\0\0Region\0"));
+    let mut ltxt = 2u32.to_le_bytes().to_vec();
+    ltxt.extend_from_slice(&REGION_LENGTH.to_le_bytes());
+    ltxt.extend_from_slice(b"rgn \0\0\0\0\0\0\0\0");
+    adtl.extend(chunk(b"ltxt", &ltxt));
+
+    let body = [
+        &b"WAVE"[..],
+        &chunk(b"fmt ", &fmt),
+        &chunk(b"data", &data),
+        &chunk(b"cue ", &cue),
+        &chunk(b"LIST", &adtl),
+    ]
+    .concat();
+    [&b"RIFF"[..], &(body.len() as u32).to_le_bytes(), &body].concat()
+}
+
+// What a render carried over: its rate, the (position, sample offset) of every marker,
+// the region length and labels, and the samples of a float32 render.
+#[derive(Debug)]
+struct Rendered {
+    sample_rate: u32,
+    markers: Vec<(u32, u32)>,
+    region_length: Option<u32>,
+    labels: Vec<String>,
+    samples: Vec<f32>,
+}
+
+fn read_rendered(path: &Path) -> Rendered {
+    let bytes = fs::read(path).unwrap();
+    assert_eq!(&bytes[0..4], b"RIFF", "{}", path.display());
+    let mut rendered = Rendered {
+        sample_rate: 0,
+        markers: Vec::new(),
+        region_length: None,
+        labels: Vec::new(),
+        samples: Vec::new(),
+    };
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32_at(&bytes, offset + 4) as usize;
+        let body = &bytes[offset + 8..offset + 8 + size];
+        match id {
+            b"fmt " => rendered.sample_rate = u32_at(body, 4),
+            b"data" => {
+                rendered.samples = body
+                    .chunks_exact(4)
+                    .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+                    .collect();
+            }
+            b"cue " => {
+                rendered.markers = body[4..]
+                    .chunks_exact(24)
+                    .map(|point| (u32_at(point, 4), u32_at(point, 20)))
+                    .collect();
+            }
+            b"LIST" if &body[0..4] == b"adtl" => {
+                let mut entry = 4;
+                while entry + 8 <= body.len() {
+                    let length = u32_at(body, entry + 4) as usize;
+                    let entry_body = &body[entry + 8..entry + 8 + length];
+                    match &body[entry..entry + 4] {
+                        b"ltxt" => rendered.region_length = Some(u32_at(entry_body, 4)),
+                        b"labl" => rendered.labels.push(
+                            String::from_utf8_lossy(&entry_body[4..])
+                                .trim_end_matches('\0')
+                                .to_string(),
+                        ),
+                        _ => {}
+                    }
+                    entry += 8 + length + length % 2;
+                }
+            }
+            _ => {}
+        }
+        offset += 8 + size + size % 2;
+    }
+    rendered
+}
+
+// Renders the marked source with `args` and returns the Rust render, then the C++ one.
+fn render(args: &[&str]) -> Vec<Rendered> {
+    let audio = project();
+    for folder in ["source", "result"] {
+        let folder = audio.join(folder);
+        if folder.exists() {
+            fs::remove_dir_all(&folder).unwrap();
+        }
+        fs::create_dir_all(&folder).unwrap();
+    }
+    fs::write(audio.join("source/markers.wav"), source_wav()).unwrap();
+    fs::write(
+        audio.join("processing/rust/rust_process_audio.rs"),
+        DELAY_PROCESSOR,
+    )
+    .unwrap();
+
+    let args = [&["--meta", "--output-format", "float32"][..], args].concat();
+    playdsp(&audio, &args);
+
+    let mut outputs: Vec<PathBuf> = fs::read_dir(audio.join("result"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    outputs.sort_by_key(|path| !path.to_string_lossy().ends_with("_rs.wav"));
+    assert_eq!(outputs.len(), 2, "{:?}", outputs);
+    outputs.iter().map(|path| read_rendered(path)).collect()
+}
+
+// Checks that `rendered` carries the source's markers and region moved to `rate`.
+fn assert_markers_at(rendered: &Rendered, rate: u32) {
+    let scale = |position: u32| (position as u64 * rate as u64 / SOURCE_RATE as u64) as u32;
+    let expected: Vec<(u32, u32)> = MARKERS
+        .iter()
+        .map(|&marker| (scale(marker), scale(marker)))
+        .collect();
+    assert_eq!(rendered.sample_rate, rate);
+    assert_eq!(rendered.markers, expected);
+    assert_eq!(rendered.region_length, Some(scale(REGION_LENGTH)));
+    assert_eq!(rendered.labels, ["Hit", "Region"]);
+}
+
+#[test]
+fn markers_keep_their_positions() {
+    for rendered in render(&[]) {
+        assert_markers_at(&rendered, SOURCE_RATE);
+        // The impulses, delayed by the Rust processor and trimmed back, are still on the
+        // markers.
+        for marker in MARKERS.map(|marker| marker as usize) {
+            let around = &rendered.samples[marker - 1..=marker + 1];
+            assert!(
+                around[0] == 0.0 && around[1] != 0.0 && around[2] == 0.0,
+                "no impulse at {}: {:?}",
+                marker,
+                around
+            );
+        }
+    }
+}
+
+#[test]
+fn markers_follow_the_render_rate() {
+    for rendered in render(&["--sample-rate", "32000"]) {
+        assert_markers_at(&rendered, 32000);
+    }
+}
+
+#[test]
+fn markers_return_with_the_restored_rate() {
+    for rendered in render(&["--sample-rate", "32000", "--restore-rate"]) {
+        assert_markers_at(&rendered, SOURCE_RATE);
+    }
+}